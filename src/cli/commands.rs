@@ -0,0 +1,657 @@
+use crate::scripts::ai::{AIGame};
+use crate::scripts::analysis::{GameAnalysis};
+use crate::scripts::bit_board::{BitBoard, GameState};
+use crate::scripts::hint::{HintSearch};
+use crate::scripts::mcts::{MctsConfig, MctsPlayer, Rollout};
+use crate::scripts::ntuple::{NTupleNetwork, TrainingConfig};
+use crate::scripts::opening_book::{OpeningBook};
+use crate::scripts::player::{self, HeuristicPlayer, HumanPlayer, Player, RandomPlayer, SolverPlayer};
+use crate::scripts::search_control::{CancelToken, ProgressCallback};
+use crate::scripts::tablebase::{Tablebase, MAX_CELLS};
+use crate::scripts::trans_table::{ReplacementPolicy, TranspositionTable};
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(feature = "parallel")]
+use crate::scripts::lazy_smp;
+#[cfg(feature = "parallel")]
+use crate::scripts::trans_table::{SharedTable};
+
+const WIDTH: usize = 7;
+const TABLE_BYTES: usize = 64 << 20;
+
+const USAGE: &str = "usage:
+    connect4-ai build-book <max ply> <output file>
+    connect4-ai solve <moves> [time limit in seconds] [--policy p] [--tablebase file]
+    connect4-ai weak-solve <moves> [--policy p]
+    connect4-ai pn-solve <moves> [max nodes] [--policy p]
+    connect4-ai mcts <moves> [iterations] [random|heuristic] [width height]
+    connect4-ai analyse <moves>
+    connect4-ai hint <moves>
+    connect4-ai build-tablebase <width> <height> <output file>
+    connect4-ai verify-tablebase <tablebase file> [check every nth position]
+    connect4-ai train <width> <height> <games> <output file> [weights file to continue from] [--seed n]
+    connect4-ai match <player> <player> [games] [seconds per move] [--seed n] [--tolerance n]
+players: solver[:table file], swindler[:table file], random, heuristic[:depth], learned:<weights file>[:depth], mcts[:playouts], human
+a solver's table file is loaded if it exists and written back after the match
+the seed is printed so the run can be repeated, solvers pick among moves up to tolerance below the best
+policies for replacing table entries: always, depth, two-tier (the default)
+with a tablebase the moves are played on a board of its size and looked up there first";
+
+//runs a command line request and returns the process exit code
+pub fn run(args: Vec<String>) -> i32 {
+    let result = match args[0].as_str() {
+        "build-book" => build_book(&args[1..]),
+        "solve" => solve(&args[1..]),
+        "weak-solve" => weak_solve(&args[1..]),
+        "pn-solve" => pn_solve(&args[1..]),
+        "mcts" => mcts(&args[1..]),
+        "analyse" => analyse(&args[1..]),
+        "hint" => hint(&args[1..]),
+        "build-tablebase" => build_tablebase(&args[1..]),
+        "verify-tablebase" => verify_tablebase(&args[1..]),
+        "train" => train(&args[1..]),
+        "match" => play_match(&args[1..]),
+        _ => Err(USAGE.to_string()),
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(message) => {
+            eprintln!("{}", message);
+            1
+        }
+    }
+}
+
+//search used by every command, spread over all cores when built with the parallel feature
+#[cfg(not(feature = "parallel"))]
+fn solver(cancel: &CancelToken, policy: ReplacementPolicy) -> impl FnMut(&mut BitBoard) -> Result<(usize, i64), String> {
+    let mut ai = AIGame::new();
+    let mut trans_table = TranspositionTable::with_policy(TABLE_BYTES, policy);
+    ai.set_cancel_token(cancel.clone());
+    ai.set_progress_callback(progress_printer());
+    move |game| {
+        trans_table.new_search();
+        Ok(ai.search(game, &mut trans_table))
+    }
+}
+
+#[cfg(feature = "parallel")]
+fn solver(cancel: &CancelToken, policy: ReplacementPolicy) -> impl FnMut(&mut BitBoard) -> Result<(usize, i64), String> {
+    let trans_table = SharedTable::with_policy(TABLE_BYTES, policy);
+    let threads = lazy_smp::default_threads();
+    let cancel = cancel.clone();
+    move |game| lazy_smp::search(game, &trans_table, threads, &cancel, Some(progress_printer()))
+}
+
+//keeps one status line about the running search up to date
+fn progress_printer() -> ProgressCallback {
+    Box::new(|progress| {
+        let show = |value: Option<String>| value.unwrap_or("-".to_string());
+        eprint!("\rsearching column {}, depth {}, best column {} ({}), {} nodes      ",
+            show(progress.root_move.map(|col| col.to_string())), progress.depth,
+            show(progress.best_move.map(|col| col.to_string())),
+            show(progress.best_score.map(|score| score.to_string())), progress.nodes);
+    })
+}
+
+//exact solver for the opening book, the table is kept between positions since every entry reaches the end of the game
+#[cfg(not(feature = "parallel"))]
+fn book_solver() -> impl FnMut(&mut BitBoard) -> (usize, i64) {
+    let ai = AIGame::new();
+    let mut trans_table = TranspositionTable::with_budget(TABLE_BYTES);
+    let mut solved = 0;
+    move |game| {
+        let result = ai.solve(game, &mut trans_table);
+        solved += 1;
+        eprint!("\rsolved {} positions      ", solved);
+        result
+    }
+}
+
+#[cfg(feature = "parallel")]
+fn book_solver() -> impl FnMut(&mut BitBoard) -> (usize, i64) {
+    let trans_table = SharedTable::with_policy(TABLE_BYTES, ReplacementPolicy::TwoTier);
+    let threads = lazy_smp::default_threads();
+    let cancel = CancelToken::new();
+    let mut solved = 0;
+    move |game| {
+        //nothing cancels the book build, so only a panicking thread gets here
+        let result = lazy_smp::solve(game, &trans_table, threads, &cancel, None).unwrap_or_else(|message| panic!("{}", message));
+        solved += 1;
+        eprint!("\rsolved {} positions      ", solved);
+        result
+    }
+}
+
+//plays a sequence of column digits such as "3344" from the empty board
+fn parse_moves(moves: &str) -> Result<BitBoard, String> {
+    parse_moves_on(BitBoard::new(), moves)
+}
+
+fn parse_moves_on(mut game: BitBoard, moves: &str) -> Result<BitBoard, String> {
+    for c in moves.chars() {
+        let col = c.to_digit(10).ok_or(format!("Invalid column: {}", c))? as usize;
+
+        if col >= game.width() || !game.is_move_valid(col) {
+            return Err(format!("Illegal move: {}", c));
+        }
+
+        if game.play_turn(col)? != GameState::Default {
+            return Err("The game is already over!".to_string());
+        }
+    }
+
+    Ok(game)
+}
+
+fn build_book(args: &[String]) -> Result<(), String> {
+    if args.len() != 2 {
+        return Err(USAGE.to_string());
+    }
+
+    let max_ply: usize = args[0].parse().map_err(|_| format!("Invalid ply: {}", args[0]))?;
+    let book = OpeningBook::build(max_ply, book_solver());
+    book.save(&args[1])?;
+    println!("Wrote {} positions up to ply {} to {}", book.len(), book.max_ply(), args[1]);
+
+    Ok(())
+}
+
+fn solve(args: &[String]) -> Result<(), String> {
+    let mut args = args.to_vec();
+    let policy = take_policy(&mut args)?;
+    let tablebase = match take_option(&mut args, "--tablebase")? {
+        Some(path) => Some(Tablebase::load(&path)?),
+        None => None,
+    };
+    let moves = args.first().map(|s| s.as_str()).unwrap_or("");
+    let mut game = match &tablebase {
+        Some(tablebase) if !TranspositionTable::fits_board(tablebase.width(), tablebase.height()) => {
+            return Err(format!("Negamax cannot search a {}x{} board", tablebase.width(), tablebase.height()));
+        }
+        Some(tablebase) => parse_moves_on(BitBoard::with_size(tablebase.width(), tablebase.height()), moves)?,
+        None => parse_moves(moves)?,
+    };
+    let cancel = CancelToken::new();
+
+    //the search stops cleanly once the time limit is reached and reports its best move so far
+    if let Some(limit) = args.get(1) {
+        let seconds: u64 = limit.parse().map_err(|_| format!("Invalid time limit: {}", limit))?;
+        let timer = cancel.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_secs(seconds));
+            timer.cancel();
+        });
+    }
+
+    let (col, score) = match tablebase {
+        //the tablebase answers every position it covers at once, so one thread is enough
+        Some(tablebase) => {
+            let mut ai = AIGame::new();
+            ai.set_cancel_token(cancel.clone());
+            ai.set_tablebase(Arc::new(tablebase));
+            ai.search(&mut game, &mut TranspositionTable::with_policy(TABLE_BYTES, policy))
+        }
+        None => solver(&cancel, policy)(&mut game)?,
+    };
+    eprintln!();
+
+    if cancel.is_cancelled() {
+        println!("interrupted, best column so far: {}", col);
+    } else {
+        println!("best column: {}, score: {}", col, score);
+    }
+
+    Ok(())
+}
+
+fn weak_solve(args: &[String]) -> Result<(), String> {
+    let mut args = args.to_vec();
+    let policy = take_policy(&mut args)?;
+    let moves = args.first().map(|s| s.as_str()).unwrap_or("");
+    let mut game = parse_moves(moves)?;
+    let mut trans_table = TranspositionTable::with_policy(TABLE_BYTES, policy);
+    let outcome = AIGame::new().weak_solve(&mut game, &mut trans_table);
+    println!("{:?} for the player to move", outcome);
+
+    Ok(())
+}
+
+fn pn_solve(args: &[String]) -> Result<(), String> {
+    let mut args = args.to_vec();
+    let policy = take_policy(&mut args)?;
+    let moves = args.first().map(|s| s.as_str()).unwrap_or("");
+    let game = parse_moves(moves)?;
+    let mut trans_table = TranspositionTable::with_policy(TABLE_BYTES, policy);
+    let ai = AIGame::new();
+    let outcome = match args.get(1) {
+        Some(n) => ai.pn_solve_within(&game, &mut trans_table, n.parse().map_err(|_| format!("Invalid node count: {}", n))?),
+        None => ai.pn_solve(&game, &mut trans_table),
+    };
+
+    match outcome {
+        Some(outcome) => println!("{:?} for the player to move", outcome),
+        None => println!("unsolved, the proof tree grew too large"),
+    }
+
+    Ok(())
+}
+
+fn mcts(args: &[String]) -> Result<(), String> {
+    let moves = args.first().map(|s| s.as_str()).unwrap_or("");
+    let iterations = match args.get(1) {
+        Some(n) => n.parse().ok().filter(|&n| n > 0).ok_or(format!("Invalid iteration count: {}", n))?,
+        None => 100000,
+    };
+    let rollout = match args.get(2).map(|s| s.as_str()) {
+        Some("random") => Rollout::Random,
+        Some("heuristic") | None => Rollout::Heuristic,
+        Some(other) => return Err(format!("Unknown rollout: {}", other)),
+    };
+    let board = match (args.get(3), args.get(4)) {
+        (Some(width), Some(height)) => {
+            let width = width.parse().map_err(|_| format!("Invalid width: {}", width))?;
+            let height = height.parse().map_err(|_| format!("Invalid height: {}", height))?;
+
+            if !BitBoard::is_size_supported(width, height) {
+                return Err("Board too large for a 64 bit mask!".to_string());
+            }
+
+            BitBoard::with_size(width, height)
+        }
+        (None, None) => BitBoard::new(),
+        _ => return Err(USAGE.to_string()),
+    };
+    let game = parse_moves_on(board, moves)?;
+    let mut config = MctsConfig::new(iterations);
+    config.rollout = rollout;
+    let mut player = MctsPlayer::new(config);
+    let col = player.best_move(&game).ok_or("The board is full!".to_string())?;
+
+    for (child, visits, reward) in player.root_stats() {
+        println!("column {}: {} visits, {:.3} reward", child, visits, reward);
+    }
+
+    println!("best column: {}", col);
+
+    Ok(())
+}
+
+//judges every move of a game, finished or not
+fn analyse(args: &[String]) -> Result<(), String> {
+    let moves = args.first().ok_or(USAGE.to_string())?;
+    let moves: Vec<usize> = moves.chars()
+        .map(|c| c.to_digit(10).map(|col| col as usize).ok_or(format!("Invalid column: {}", c)))
+        .collect::<Result<_, _>>()?;
+    let board = BitBoard::new();
+    let mut analysis = GameAnalysis::new(board.width(), board.height(), &moves, AIGame::new(), TABLE_BYTES)?;
+
+    while !analysis.step() {
+        eprint!("\ranalysing move {}/{}", analysis.completed() + 1, analysis.total());
+    }
+
+    eprintln!();
+
+    for annotation in analysis.annotations() {
+        println!("{}", annotation.describe());
+    }
+
+    println!("{} flagged moves", analysis.flagged().count());
+
+    Ok(())
+}
+
+//best column with the reasons behind it
+fn hint(args: &[String]) -> Result<(), String> {
+    let moves = args.first().map(|s| s.as_str()).unwrap_or("");
+    let game = parse_moves(moves)?;
+    let mut search = HintSearch::new(&game, AIGame::new(), TABLE_BYTES);
+
+    while !search.step() {}
+
+    let hint = search.hint();
+
+    for reason in &hint.reasons {
+        println!("{:?}: {}", reason, reason.describe());
+    }
+
+    match hint.score {
+        Some(score) => println!("best column: {}, score: {}", hint.col, score),
+        None => println!("best column: {} (from the opening book)", hint.col),
+    }
+
+    Ok(())
+}
+
+fn build_tablebase(args: &[String]) -> Result<(), String> {
+    if args.len() != 3 {
+        return Err(USAGE.to_string());
+    }
+
+    let width: usize = args[0].parse().map_err(|_| format!("Invalid width: {}", args[0]))?;
+    let height: usize = args[1].parse().map_err(|_| format!("Invalid height: {}", args[1]))?;
+
+    if !Tablebase::is_size_supported(width, height) {
+        return Err(format!("Tablebases need at most {} columns and fewer than 64 bits per board", WIDTH));
+    }
+
+    if width * height > MAX_CELLS {
+        return Err(format!("Tablebases are limited to {} cells, {}x{} has too many positions to generate", MAX_CELLS, width, height));
+    }
+
+    let tablebase = Tablebase::generate(width, height);
+    tablebase.save(&args[2])?;
+    println!("wrote {} positions to {}", tablebase.len(), args[2]);
+
+    Ok(())
+}
+
+//learns an evaluation by self-play and writes the weights to a file
+fn train(args: &[String]) -> Result<(), String> {
+    let mut args = args.to_vec();
+    let seed = take_seed(&mut args)?;
+
+    if args.len() < 4 {
+        return Err(USAGE.to_string());
+    }
+
+    let width: usize = args[0].parse().map_err(|_| format!("Invalid width: {}", args[0]))?;
+    let height: usize = args[1].parse().map_err(|_| format!("Invalid height: {}", args[1]))?;
+    let games: usize = args[2].parse().map_err(|_| format!("Invalid game count: {}", args[2]))?;
+
+    if !BitBoard::is_size_supported(width, height) {
+        return Err("Board too large for a 64 bit mask!".to_string());
+    }
+
+    let mut network = match args.get(4) {
+        Some(path) => NTupleNetwork::load(path)?,
+        None => NTupleNetwork::new(width, height),
+    };
+
+    if network.width() != width || network.height() != height {
+        return Err(format!("{} was trained for a {}x{} board", args[4], network.width(), network.height()));
+    }
+
+    let mut config = TrainingConfig::new(games);
+    config.seed = seed;
+    network.train(&config, |played| {
+        if played % 1000 == 0 || played == games {
+            eprint!("\rplayed {}/{} games", played, games);
+        }
+    });
+    eprintln!();
+
+    network.save(&args[3])?;
+    println!("wrote weights for a {}x{} board to {}", width, height, args[3]);
+
+    Ok(())
+}
+
+//compares negamax against the tablebase, which is exact
+fn verify_tablebase(args: &[String]) -> Result<(), String> {
+    let path = args.first().ok_or(USAGE.to_string())?;
+    let step: usize = match args.get(1) {
+        Some(n) => n.parse().map_err(|_| format!("Invalid step: {}", n))?,
+        None => 1,
+    };
+    let tablebase = Tablebase::load(path)?;
+
+    if !TranspositionTable::fits_board(tablebase.width(), tablebase.height()) {
+        return Err(format!("Negamax cannot search a {}x{} board", tablebase.width(), tablebase.height()));
+    }
+
+    let ai = AIGame::new();
+    let mut trans_table = TranspositionTable::with_budget(TABLE_BYTES);
+    let mut checked = 0;
+    let mut mismatches = 0;
+
+    for (mut game, entry) in tablebase.positions().step_by(step.max(1)) {
+        let (_, score) = ai.search(&mut game, &mut trans_table);
+        checked += 1;
+
+        if score != entry.score(&game) {
+            mismatches += 1;
+            println!("mismatch at key {}: negamax {}, tablebase {} ({:?} in {})",
+                game.get_unique_key(), score, entry.score(&game), entry.outcome, entry.distance);
+        }
+    }
+
+    println!("checked {} positions of the {}x{} board, {} mismatches", checked, tablebase.width(), tablebase.height(), mismatches);
+
+    if mismatches > 0 {
+        return Err("negamax disagrees with the tablebase".to_string());
+    }
+
+    Ok(())
+}
+
+//removes "name value" from args, None if the option was not given
+fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>, String> {
+    match args.iter().position(|arg| arg == name) {
+        Some(idx) if idx + 1 < args.len() => {
+            let value = args.remove(idx + 1);
+            args.remove(idx);
+            Ok(Some(value))
+        }
+        Some(_) => Err(format!("Missing value for {}", name)),
+        None => Ok(None),
+    }
+}
+
+//seed given with --seed or drawn at random, printed either way so the run can be repeated
+fn take_seed(args: &mut Vec<String>) -> Result<u64, String> {
+    let seed = match take_option(args, "--seed")? {
+        Some(seed) => seed.parse().map_err(|_| format!("Invalid seed: {}", seed))?,
+        None => rand::random(),
+    };
+    println!("seed {}", seed);
+
+    Ok(seed)
+}
+
+//replacement policy given with --policy, two-tier if none was
+fn take_policy(args: &mut Vec<String>) -> Result<ReplacementPolicy, String> {
+    match take_option(args, "--policy")?.as_deref() {
+        Some("always") => Ok(ReplacementPolicy::AlwaysReplace),
+        Some("depth") => Ok(ReplacementPolicy::DepthPreferred),
+        Some("two-tier") | None => Ok(ReplacementPolicy::TwoTier),
+        Some(other) => Err(format!("Unknown replacement policy: {}", other)),
+    }
+}
+
+fn parse_player(spec: &str, seed: u64, tolerance: i64) -> Result<Box<dyn Player>, String> {
+    if let Some(learned) = spec.strip_prefix("learned:") {
+        return parse_learned_player(learned, seed);
+    }
+
+    if let Some((kind, path)) = table_file(spec) {
+        let mut player = parse_player(kind, seed, tolerance)?;
+
+        if std::path::Path::new(path).exists() {
+            let bytes = std::fs::read(path).map_err(|err| err.to_string())?;
+            player.load_memory(&bytes)?;
+        }
+
+        return Ok(player);
+    }
+
+    let mut parts = spec.splitn(2, ':');
+    let kind = parts.next().unwrap_or("");
+    let setting = match parts.next() {
+        Some(value) => Some(value.parse::<usize>().map_err(|_| format!("Invalid player setting: {}", spec))?),
+        None => None,
+    };
+
+    match kind {
+        "solver" => {
+            let mut ai = AIGame::with_seed(seed);
+            ai.tolerance = tolerance;
+            Ok(Box::new(SolverPlayer::new(ai)))
+        }
+        "swindler" => {
+            let mut ai = AIGame::with_seed(seed);
+            ai.tolerance = tolerance;
+            ai.swindle = true;
+            Ok(Box::new(SolverPlayer::new(ai)))
+        }
+        "random" => Ok(Box::new(RandomPlayer::new(seed))),
+        "heuristic" => Ok(Box::new(HeuristicPlayer::new(setting.unwrap_or(6), seed))),
+        "mcts" => {
+            if setting == Some(0) {
+                return Err(format!("Invalid playout count: {}", spec));
+            }
+
+            let mut config = MctsConfig::new(setting.unwrap_or(20000));
+            config.seed = seed;
+            Ok(Box::new(MctsPlayer::new(config)))
+        }
+        "human" => Ok(Box::new(HumanPlayer::new())),
+        _ => Err(format!("Unknown player: {}", spec)),
+    }
+}
+
+//weights file, optionally followed by the search depth
+fn parse_learned_player(spec: &str, seed: u64) -> Result<Box<dyn Player>, String> {
+    let (path, depth) = match spec.rsplit_once(':') {
+        Some((path, depth)) if depth.parse::<usize>().is_ok() => (path, depth.parse().unwrap()),
+        _ => (spec, 6),
+    };
+    let network = NTupleNetwork::load(path)?;
+    let board = BitBoard::new();
+
+    if network.width() != board.width() || network.height() != board.height() {
+        return Err(format!("{} was trained for a {}x{} board", path, network.width(), network.height()));
+    }
+
+    Ok(Box::new(HeuristicPlayer::with_evaluator(depth, seed, Box::new(network), "Learned")))
+}
+
+//splits a spec like solver:table.bin into the solver kind and its table file
+fn table_file(spec: &str) -> Option<(&str, &str)> {
+    match spec.split_once(':') {
+        Some((kind @ ("solver" | "swindler"), path)) => Some((kind, path)),
+        _ => None,
+    }
+}
+
+//plays games between two players, who take turns starting
+fn play_match(args: &[String]) -> Result<(), String> {
+    let mut args = args.to_vec();
+    let seed = take_seed(&mut args)?;
+    let tolerance: i64 = match take_option(&mut args, "--tolerance")? {
+        Some(n) => n.parse().ok().filter(|&n| n >= 0).ok_or(format!("Invalid tolerance: {}", n))?,
+        None => 0,
+    };
+
+    if args.len() < 2 {
+        return Err(USAGE.to_string());
+    }
+
+    let games: usize = match args.get(2) {
+        Some(n) => n.parse().map_err(|_| format!("Invalid game count: {}", n))?,
+        None => 2,
+    };
+    let seconds: f64 = match args.get(3) {
+        Some(n) => n.parse().map_err(|_| format!("Invalid time limit: {}", n))?,
+        None => 5.0,
+    };
+    let budget = Duration::from_secs_f64(seconds);
+    //the players get different seeds so two copies of the same engine do not mirror each other
+    let mut players = [parse_player(&args[0], seed, tolerance)?, parse_player(&args[1], seed.wrapping_add(1), tolerance)?];
+    let mut wins = [0, 0];
+    let mut draws = 0;
+
+    for number in 0..games {
+        let first = number % 2;
+        let mut game = BitBoard::new();
+        let mut moves = String::new();
+
+        for player in players.iter_mut() {
+            player.new_game();
+        }
+
+        let state = loop {
+            let turn = if game.red_turn {first} else {1 - first};
+            let col = next_move(players[turn].as_mut(), &game, budget)?;
+            moves.push_str(&col.to_string());
+            let state = game.play_turn(col)?;
+
+            if state != GameState::Default {
+                break state;
+            }
+        };
+
+        let result = match state {
+            GameState::Win => {
+                wins[first] += 1;
+                "red wins"
+            }
+            GameState::Loss => {
+                wins[1 - first] += 1;
+                "yellow wins"
+            }
+            _ => {
+                draws += 1;
+                "draw"
+            }
+        };
+
+        println!("game {}: {} (red) vs {} (yellow), {} after {}",
+            number + 1, players[first].name(), players[1 - first].name(), result, moves);
+    }
+
+    println!("{}: {} wins, {}: {} wins, {} draws", players[0].name(), wins[0], players[1].name(), wins[1], draws);
+
+    for (spec, player) in args.iter().zip(players.iter()) {
+        if let (Some((_, path)), Some(bytes)) = (table_file(spec), player.save_memory(usize::MAX)) {
+            std::fs::write(path, bytes).map_err(|err| err.to_string())?;
+        }
+    }
+
+    Ok(())
+}
+
+//waits for the player's move, asking on the terminal when a person is playing
+fn next_move(player: &mut dyn Player, game: &BitBoard, budget: Duration) -> Result<usize, String> {
+    loop {
+        if let Some(col) = player::choose_move(player, game, budget) {
+            return Ok(col);
+        }
+
+        if !player.is_human() {
+            return Err(format!("{} found no move", player.name()));
+        }
+
+        print_board(game);
+        eprint!("your move (0-{}): ", game.width() - 1);
+        let mut line = String::new();
+
+        if std::io::stdin().read_line(&mut line).map_err(|err| err.to_string())? == 0 {
+            return Err("No more input".to_string());
+        }
+
+        if let Ok(col) = line.trim().parse() {
+            player.input(col);
+        }
+    }
+}
+
+fn print_board(game: &BitBoard) {
+    for row in (0..game.height()).rev() {
+        let line: String = (0..game.width()).map(|col| {
+            let cell = game.bottom_col_mask(col) << row;
+
+            if game.total_mask & cell == 0 {
+                '.'
+            } else if game.player_mask & cell != 0 {
+                'X'
+            } else {
+                'O'
+            }
+        }).collect();
+        eprintln!("{}", line);
+    }
+
+    eprintln!("{}", (0..game.width()).map(|col| col.to_string()).collect::<String>());
+}
@@ -1 +1,2 @@
+pub mod storage;
 pub mod ui;
\ No newline at end of file
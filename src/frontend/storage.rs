@@ -0,0 +1,156 @@
+use yew::format::Text;
+use yew::services::storage::{Area, StorageService};
+
+//local storage only holds strings, a few megabytes per site, so bytes go in as base64 split over several keys
+const CHUNK_CHARS: usize = 1 << 18;
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+//stores bytes under name, replacing whatever was saved there before
+pub fn save(name: &str, bytes: &[u8]) -> Result<(), String> {
+    let mut storage = StorageService::new(Area::Local)?;
+    remove_chunks(&mut storage, name);
+
+    let text = encode(bytes);
+    let chunks: Vec<&[u8]> = text.as_bytes().chunks(CHUNK_CHARS).collect();
+
+    for (i, chunk) in chunks.iter().enumerate() {
+        let chunk: Text = Ok(String::from_utf8(chunk.to_vec()).unwrap());
+        storage.store(&chunk_key(name, i), chunk);
+    }
+
+    let count: Text = Ok(chunks.len().to_string());
+    storage.store(&count_key(name), count);
+
+    Ok(())
+}
+
+//bytes saved under name, None if nothing was saved or it could not be read back
+pub fn load(name: &str) -> Option<Vec<u8>> {
+    let storage = StorageService::new(Area::Local).ok()?;
+    let count: Text = storage.restore(&count_key(name));
+    let count: usize = count.ok()?.parse().ok()?;
+    let mut text = String::new();
+
+    for i in 0..count {
+        let chunk: Text = storage.restore(&chunk_key(name, i));
+        text.push_str(&chunk.ok()?);
+    }
+
+    decode(&text)
+}
+
+fn remove_chunks(storage: &mut StorageService, name: &str) {
+    let count: Text = storage.restore(&count_key(name));
+
+    if let Some(count) = count.ok().and_then(|count| count.parse::<usize>().ok()) {
+        for i in 0..count {
+            storage.remove(&chunk_key(name, i));
+        }
+    }
+
+    storage.remove(&count_key(name));
+}
+
+fn count_key(name: &str) -> String {
+    format!("{}.chunks", name)
+}
+
+fn chunk_key(name: &str, i: usize) -> String {
+    format!("{}.{}", name, i)
+}
+
+//standard base64 with padding
+fn encode(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for group in bytes.chunks(3) {
+        let n = group.iter().enumerate().fold(0u32, |n, (i, &byte)| n | (byte as u32) << (16 - 8 * i));
+
+        for i in 0..4 {
+            if i <= group.len() {
+                text.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 63] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+
+    text
+}
+
+fn decode(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(4) {
+        return None;
+    }
+
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    let groups = text.len() / 4;
+
+    for (g, group) in text.as_bytes().chunks(4).enumerate() {
+        let padding = group.iter().rev().take_while(|&&c| c == b'=').count();
+
+        //padding may only end the text
+        if padding > 2 || (padding > 0 && g + 1 < groups) {
+            return None;
+        }
+
+        let mut n = 0u32;
+
+        for (i, &c) in group[..4 - padding].iter().enumerate() {
+            n |= sextet(c)? << (18 - 6 * i);
+        }
+
+        for i in 0..3 - padding {
+            bytes.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+
+    Some(bytes)
+}
+
+//value of a base64 character
+fn sextet(c: u8) -> Option<u32> {
+    let value = match c {
+        b'A'..=b'Z' => c - b'A',
+        b'a'..=b'z' => c - b'a' + 26,
+        b'0'..=b'9' => c - b'0' + 52,
+        b'+' => 62,
+        b'/' => 63,
+        _ => return None,
+    };
+
+    Some(value as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoding_matches_standard_base64() {
+        assert_eq!(encode(b""), "");
+        assert_eq!(encode(b"f"), "Zg==");
+        assert_eq!(encode(b"fo"), "Zm8=");
+        assert_eq!(encode(b"foo"), "Zm9v");
+        assert_eq!(encode(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn decoding_undoes_encoding() {
+        for length in 0..10 {
+            let bytes: Vec<u8> = (0..length).map(|i| (i * 97 + 200) as u8).collect();
+            assert_eq!(decode(&encode(&bytes)), Some(bytes));
+        }
+
+        let every_byte: Vec<u8> = (0..=255).collect();
+        assert_eq!(decode(&encode(&every_byte)), Some(every_byte));
+    }
+
+    #[test]
+    fn broken_text_is_rejected() {
+        assert_eq!(decode("Zm9"), None);
+        assert_eq!(decode("Zm9v!A=="), None);
+        assert_eq!(decode("Z==="), None);
+        assert_eq!(decode("Zg==Zg=="), None);
+    }
+}
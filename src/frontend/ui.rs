@@ -1,24 +1,176 @@
+use std::time::Duration;
 use yew::prelude::*;
+use yew::services::timeout::{TimeoutService, TimeoutTask};
 // use crate::scripts::array_board::{ArrayBoard, Cell, GameState};
 use crate::scripts::bit_board::{BitBoard, GameState};
-use crate::scripts::ai::{AIGame};
-use crate::scripts::trans_table::{TranspositionTable};
+use crate::scripts::ai::{AIGame, Outcome};
+use crate::scripts::analysis::{GameAnalysis};
+use crate::scripts::evaluation::{ColumnResult, PositionEvaluation};
+use crate::scripts::hint::{Hint, HintSearch};
+use crate::scripts::mcts::{MctsConfig, MctsPlayer};
+use crate::scripts::player::{HeuristicPlayer, HumanPlayer, Player, RandomPlayer, SolverPlayer};
+use crate::frontend::storage;
 
 const HEIGHT: usize = 6;
 const WIDTH: usize = 7;
+const MOVE_TIME: Duration = Duration::from_secs(20); //an engine plays its best move so far after this long
+const HELPER_TABLE_BYTES: usize = 8 << 20; //table of the searches behind hints, analysis and evaluation
+const MEMORY_NAME: &str = "connect4.engine"; //local storage name of what the engines learned
+const MEMORY_BYTES: usize = 1 << 20; //well within the few megabytes a site may keep in local storage
+const LEVELS: [&str; 3] = ["Easy", "Medium", "Hard"]; //engine strengths in spectated games
+const SOLVER_TIMES: [Duration; 3] = [Duration::from_millis(200), Duration::from_secs(2), Duration::from_secs(10)];
+const HEURISTIC_DEPTHS: [usize; 3] = [2, 4, 6];
+const MCTS_PLAYOUTS: [usize; 3] = [1000, 10000, 50000];
+const RESTART_DELAY: Duration = Duration::from_secs(3); //time to look at a finished spectated game before the next one
+
+//who sits at the board
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum Mode {
+    AgainstEngine,
+    HotSeat, //two people taking turns on the same screen
+    Spectator, //two engines playing each other
+}
+
+//engines to pick from for a spectated game
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum Engine {
+    Solver,
+    Mcts,
+    Heuristic,
+    Random,
+}
+
+impl Engine {
+    fn next(self) -> Self {
+        match self {
+            Engine::Solver => Engine::Mcts,
+            Engine::Mcts => Engine::Heuristic,
+            Engine::Heuristic => Engine::Random,
+            Engine::Random => Engine::Solver,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Engine::Solver => "Solver",
+            Engine::Mcts => "MCTS",
+            Engine::Heuristic => "Heuristic",
+            Engine::Random => "Random",
+        }
+    }
+
+    //a fresh player at one of the LEVELS, stronger levels think longer or further ahead
+    fn player(self, level: usize) -> Box<dyn Player> {
+        let seed = rand::random();
+
+        match self {
+            Engine::Solver => Box::new(SolverPlayer::new(AIGame::with_seed(seed))),
+            Engine::Mcts => {
+                let mut config = MctsConfig::new(MCTS_PLAYOUTS[level]);
+                config.seed = seed;
+                Box::new(MctsPlayer::new(config))
+            }
+            Engine::Heuristic => Box::new(HeuristicPlayer::new(HEURISTIC_DEPTHS[level], seed)),
+            Engine::Random => Box::new(RandomPlayer::new(seed)),
+        }
+    }
+}
+
+//what a spectated game does next
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Advance {
+    Wait,
+    Move, //the engine to move starts thinking
+    Restart,
+}
+
+//pause and restart settings of a spectated game
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+struct Playback {
+    paused: bool, //the game only moves on when stepped
+    auto_restart: bool, //a finished game is followed by the next one
+}
+
+impl Playback {
+    //when the move timer fires or, with stepped, Step is clicked. a paused game goes one move at a time,
+    //stepping past the end starts the next game whether or not it restarts by itself
+    fn advance(&self, stepped: bool, game_over: bool) -> Advance {
+        if self.paused != stepped {
+            return Advance::Wait;
+        }
+
+        match (game_over, stepped || self.auto_restart) {
+            (false, _) => Advance::Move,
+            (true, true) => Advance::Restart,
+            (true, false) => Advance::Wait,
+        }
+    }
+
+    //moves taken back or played again stop a spectated game, so it can be stepped through from there
+    fn history_changed(&mut self, mode: Mode) {
+        if mode == Mode::Spectator {
+            self.paused = true;
+        }
+    }
+}
 
 pub struct Connect4 {
     link: ComponentLink<Self>,
     // array_board: ArrayBoard,
     bit_board: BitBoard,
     game_over: bool,
-    ai: AIGame,
-    trans_table: TranspositionTable,
+    players: [Box<dyn Player>; 2], //the player moving first, then the other one
+    mode: Mode,
+    bench: Option<Box<dyn Player>>, //the engine facing people while it sits out, so it keeps its table
+    human_red: bool, //colour the person plays
+    human_first: bool, //the person makes the opening move
+    swindle: bool, //the engine facing people tries tricks once it is lost, see AIGame::swindle
+    names: [String; 2], //names the players typed in, red then yellow, only used in hot seat games
+    engines: [Engine; 2], //red then yellow in spectated games
+    levels: [usize; 2], //index into LEVELS for each of engines
+    delay: Duration, //pause between the moves of a spectated game
+    playback: Playback, //pause and restart of spectated games
+    next_task: Option<TimeoutTask>, //starts the next move of a spectated game after the delay
+    thinking: bool, //an engine is working on a move, one step at a time so the page stays responsive
+    step_task: Option<TimeoutTask>, //keeps the next thinking step scheduled
+    time_task: Option<TimeoutTask>, //ends the move when the engine runs out of time
+    moves: Vec<usize>, //columns played so far
+    redo: Vec<usize>, //columns taken back, the next one to play again last
+    analysis: Option<GameAnalysis>, //post-game analysis, running or finished
+    analysis_task: Option<TimeoutTask>, //keeps the next analysis step scheduled
+    hint_search: Option<HintSearch>, //running search for a hint
+    hint: Option<Hint>, //hint for the current position
+    hint_task: Option<TimeoutTask>, //keeps the next hint search step scheduled
+    evaluation: Option<PositionEvaluation>, //live evaluation of the current position, while switched on
+    evaluation_task: Option<TimeoutTask>, //keeps the next evaluation step scheduled
+    notice: Option<String>, //problem shown with the game state message until the next move or game
 }
 
 pub enum Msg {
     ColumnClicked(usize),
     NewGameClicked,
+    ThinkStep,
+    MoveNowClicked,
+    AnalyseClicked,
+    AnalysisStep,
+    HintClicked,
+    HintStep,
+    ColourClicked,
+    StarterClicked,
+    SwindleToggled,
+    ModeClicked,
+    NameChanged(usize, String),
+    EvaluationToggled,
+    EvaluationStep,
+    EngineClicked(usize),
+    LevelClicked(usize),
+    DelayChanged(u64),
+    PauseClicked,
+    StepClicked,
+    AutoRestartToggled,
+    NextMove,
+    UndoClicked,
+    RedoClicked,
 }
 
 impl Component for Connect4 {
@@ -26,46 +178,251 @@ impl Component for Connect4 {
     type Properties = ();
 
     fn create(_: Self::Properties, link: ComponentLink<Self>) -> Self {
-        Connect4 {
+        let mut connect4 = Connect4 {
             link,
             // array_board: ArrayBoard::new(),
             bit_board: BitBoard::new(),
             game_over: false,
-            ai: AIGame::new(),
-            trans_table: TranspositionTable::new(8388593),
-        }
+            players: [Box::new(HumanPlayer::new()), Box::new(SolverPlayer::new(opponent(false)))],
+            mode: Mode::AgainstEngine,
+            bench: None,
+            human_red: true,
+            human_first: true,
+            swindle: false,
+            names: [String::new(), String::new()],
+            engines: [Engine::Solver, Engine::Mcts],
+            levels: [1, 1],
+            delay: Duration::from_millis(500),
+            playback: Playback {paused: false, auto_restart: true},
+            next_task: None,
+            thinking: false,
+            step_task: None,
+            time_task: None,
+            moves: Vec::new(),
+            redo: Vec::new(),
+            analysis: None,
+            analysis_task: None,
+            hint_search: None,
+            hint: None,
+            hint_task: None,
+            evaluation: None,
+            evaluation_task: None,
+            notice: None,
+        };
+
+        connect4.load_memory();
+        connect4
     }
 
     fn update(&mut self, msg: Self::Message) -> ShouldRender {
         match msg {
             Msg::ColumnClicked(column) => {
-                if !self.game_over {
-                    if let Ok(state1) = self.bit_board.play_turn(column) {
-                        self.handle_game_state(state1);
-                        if !self.game_over {
-                            if let Ok(state2) = self.ai.make_move(&mut self.bit_board, &mut self.trans_table) {
-                                self.handle_game_state(state2);
-                            }else {
-                                println!("Column full, choose another column");
-                            }
-                        }
+                let turn = self.turn();
+
+                if !self.game_over && !self.thinking && self.players[turn].is_human() {
+                    self.players[turn].input(column);
+
+                    if let Some(col) = self.players[turn].think(&self.bit_board) {
+                        self.play_move(col);
                     } else {
-                        println!("Column full, choose another column");
+                        self.notice = Some("Column full, choose another column".to_string());
                     }
                 }
             }
             Msg::NewGameClicked => {
                 self.start_new_game();
             }
+            Msg::ThinkStep => {
+                let turn = self.turn();
+
+                if self.thinking {
+                    if let Some(col) = self.players[turn].think(&self.bit_board) {
+                        self.play_move(col);
+                    } else {
+                        self.schedule_think_step();
+                    }
+                }
+            }
+            Msg::MoveNowClicked => {
+                let turn = self.turn();
+
+                if self.thinking {
+                    if let Some(col) = self.players[turn].move_now(&self.bit_board) {
+                        self.play_move(col);
+                    }
+                }
+            }
+            Msg::AnalyseClicked => {
+                if self.game_over && self.analysis.is_none() {
+                    let analysis = GameAnalysis::new(WIDTH, HEIGHT, &self.moves, AIGame::new(), HELPER_TABLE_BYTES);
+
+                    if let Ok(analysis) = analysis {
+                        self.analysis = Some(analysis);
+                        self.schedule_analysis_step();
+                    }
+                }
+            }
+            Msg::HintClicked => {
+                let turn = self.turn();
+
+                if !self.game_over && self.players[turn].is_human() && self.hint_search.is_none() {
+                    self.hint = None;
+                    self.hint_search = Some(HintSearch::new(&self.bit_board, AIGame::new(), HELPER_TABLE_BYTES));
+                    self.schedule_hint_step();
+                }
+            }
+            Msg::HintStep => {
+                self.hint_task = None;
+
+                if let Some(search) = self.hint_search.as_mut() {
+                    if search.step() {
+                        self.hint = Some(search.hint());
+                        self.hint_search = None;
+                    } else {
+                        self.schedule_hint_step();
+                    }
+                }
+            }
+            Msg::ColourClicked => {
+                self.human_red = !self.human_red;
+                self.start_new_game();
+            }
+            Msg::StarterClicked => {
+                self.human_first = !self.human_first;
+                self.start_new_game();
+            }
+            Msg::SwindleToggled => {
+                self.swindle = !self.swindle;
+                let seat = if self.players[0].is_human() {1} else {0};
+                self.players[seat] = Box::new(SolverPlayer::new(opponent(self.swindle)));
+                //the new engine picks up where the old one left off
+                self.load_memory();
+                self.start_new_game();
+            }
+            Msg::ModeClicked => {
+                self.mode = match self.mode {
+                    Mode::AgainstEngine => Mode::HotSeat,
+                    Mode::HotSeat => Mode::Spectator,
+                    Mode::Spectator => Mode::AgainstEngine,
+                };
+                self.start_new_game();
+            }
+            Msg::NameChanged(seat, name) => {
+                self.names[seat] = name;
+            }
+            Msg::EvaluationToggled => {
+                if self.evaluation.is_some() {
+                    self.evaluation = None;
+                    self.evaluation_task = None;
+                } else {
+                    self.evaluation = Some(PositionEvaluation::new(&self.bit_board, AIGame::new(), HELPER_TABLE_BYTES));
+                    self.schedule_evaluation_step();
+                }
+            }
+            Msg::EvaluationStep => {
+                self.evaluation_task = None;
+
+                if let Some(evaluation) = self.evaluation.as_mut() {
+                    if !evaluation.step() {
+                        self.schedule_evaluation_step();
+                    }
+                }
+            }
+            Msg::EngineClicked(seat) => {
+                self.engines[seat] = self.engines[seat].next();
+                self.players[seat] = self.engines[seat].player(self.levels[seat]);
+                self.start_new_game();
+            }
+            Msg::LevelClicked(seat) => {
+                self.levels[seat] = (self.levels[seat] + 1) % LEVELS.len();
+                self.players[seat] = self.engines[seat].player(self.levels[seat]);
+                self.start_new_game();
+            }
+            Msg::DelayChanged(millis) => {
+                self.delay = Duration::from_millis(millis);
+            }
+            Msg::PauseClicked => {
+                self.playback.paused = !self.playback.paused;
+                self.next_task = None;
+
+                //a move being thought about carries on, the next one waits for it
+                if !self.playback.paused && !self.thinking {
+                    self.next_turn();
+                }
+            }
+            Msg::StepClicked => {
+                if !self.thinking {
+                    self.advance(true);
+                }
+            }
+            Msg::AutoRestartToggled => {
+                self.playback.auto_restart = !self.playback.auto_restart;
+
+                if self.game_over && !self.playback.paused {
+                    self.next_turn();
+                }
+            }
+            Msg::NextMove => {
+                self.next_task = None;
+
+                if self.mode == Mode::Spectator && !self.thinking {
+                    self.advance(false);
+                }
+            }
+            Msg::UndoClicked => {
+                if let Some(target) = self.undo_target() {
+                    self.stop_turn();
+
+                    while self.moves.len() > target {
+                        let col = self.moves.pop().unwrap();
+                        self.redo.push(col);
+                    }
+
+                    //the board cannot take back a win, so the game is played again up to the target
+                    self.bit_board = BitBoard::new();
+
+                    for &col in self.moves.iter() {
+                        let _ = self.bit_board.play_turn(col);
+                    }
+
+                    self.game_over = false;
+                    self.after_history_change();
+                }
+            }
+            Msg::RedoClicked => {
+                if !self.redo.is_empty() && !self.game_over {
+                    self.stop_turn();
+
+                    //against the engine its reply comes back with the person's move, as it was taken back
+                    while let Some(col) = self.redo.pop() {
+                        if !self.apply_move(col) {
+                            break;
+                        }
+
+                        if self.mode != Mode::AgainstEngine || self.game_over || self.players[self.turn()].is_human() {
+                            break;
+                        }
+                    }
+
+                    self.after_history_change();
+                }
+            }
+            Msg::AnalysisStep => {
+                self.analysis_task = None;
+
+                if let Some(analysis) = self.analysis.as_mut() {
+                    if !analysis.step() {
+                        self.schedule_analysis_step();
+                    }
+                }
+            }
         }
 
         true
     }
 
     fn change(&mut self, _: Self::Properties) -> ShouldRender {
-        // self.array_board = ArrayBoard::new();
-        self.bit_board = BitBoard::new();
-        self.trans_table = TranspositionTable::new(8388593);
+        self.start_new_game();
         true
     }
 
@@ -74,6 +431,8 @@ impl Component for Connect4 {
         let game_result = self.render_game_state_message();
         let game_turn = self.render_turn_message();
         let new_game = self.render_new_game();
+        let red = if self.first_red() {0} else {1};
+        let players = format!("Red: {}, Yellow: {}", self.players[red].name(), self.players[1 - red].name());
 
         html! {
             <>
@@ -81,6 +440,7 @@ impl Component for Connect4 {
                     <div class="board">
                         { for (0..HEIGHT).map(|row| self.render_row(row)) }
                     </div>
+                    { self.render_column_scores() }
                     <div class="buttons">
                         { for (0..WIDTH).map(|column| self.render_button(column)) }
                     </div>
@@ -89,7 +449,16 @@ impl Component for Connect4 {
                         { game_result }
                     </div>
                     
+                    { self.render_evaluation() }
+                    { self.render_hint() }
                     { new_game }
+                    { self.render_history() }
+                    { self.render_settings() }
+                    { self.render_playback() }
+                    { self.render_analysis() }
+                    <div class="players">
+                        { players }
+                    </div>
                 </div>
                 <style>
                     {css}
@@ -99,22 +468,104 @@ impl Component for Connect4 {
     }
 }
 
+//the engine facing people, with swindle it still makes them work for the win when it is lost
+fn opponent(swindle: bool) -> AIGame {
+    let mut ai = AIGame::new();
+    ai.swindle = swindle;
+    ai
+}
+
+//the board reports wins for the player moving first, the message speaks to the person.
+//labels name the players moving first and second, a notice replaces or follows the state
+fn state_text(state: GameState, mode: Mode, human_first: bool, labels: &[String; 2], notice: Option<&str>) -> String {
+    let text = match (state, mode, human_first) {
+        (GameState::Win, Mode::HotSeat, _) | (GameState::Win, Mode::Spectator, _) => format!("{} won! :D", labels[0]),
+        (GameState::Loss, Mode::HotSeat, _) | (GameState::Loss, Mode::Spectator, _) => format!("{} won! :D", labels[1]),
+        (GameState::Win, _, true) | (GameState::Loss, _, false) => "You won! :D".to_string(),
+        (GameState::Win, _, false) | (GameState::Loss, _, true) => "You lost! :(".to_string(),
+        (GameState::Tie, _, _) => "Tie! :|".to_string(),
+        (GameState::Default, _, _) => "Think carefully!".to_string(),
+    };
+
+    match (notice, state) {
+        (Some(notice), GameState::Default) => notice.to_string(),
+        (Some(notice), _) => format!("{} {}", text, notice),
+        (None, _) => text,
+    }
+}
+
+//what to call the player with the given colour: the name typed in for a hot seat game, otherwise the colour.
+//names are red's then yellow's
+fn player_label(mode: Mode, colour: &str, names: &[String; 2]) -> String {
+    let name = if colour == "Red" {&names[0]} else {&names[1]};
+
+    if mode == Mode::HotSeat && !name.trim().is_empty() {
+        format!("{} ({})", name.trim(), colour)
+    } else {
+        colour.to_string()
+    }
+}
+
+//line under the evaluation bar, mover and opponent name the side to move and the other one
+fn evaluation_text(evaluation: &PositionEvaluation, mover: &str, opponent: &str) -> String {
+    if !evaluation.is_done() {
+        return format!("Evaluating... {}/{} columns", evaluation.columns().len(), evaluation.board().width());
+    }
+
+    //results are for the side to move, an unknown column might still beat a proven one
+    let complete = evaluation.columns().iter().all(|result| *result != ColumnResult::Unknown);
+    let moves = evaluation.best()
+        .and_then(|(_, result)| result.score())
+        .and_then(|score| evaluation.moves_to_win(score))
+        .map_or(String::new(), |moves| format!(" in {} moves", moves));
+
+    match evaluation.best().and_then(|(col, result)| Some((col, result.outcome()?))) {
+        Some((col, Outcome::Win)) => format!("{} can force a win{} (column {})", mover, moves, col + 1),
+        Some((_, Outcome::Draw)) if complete => "Draw with best play".to_string(),
+        Some((_, Outcome::Draw)) => format!("{} can hold at least a draw", mover),
+        Some((_, Outcome::Loss)) if complete => format!("{} can force a win{}", opponent, moves),
+        _ => "Unclear, too early in the game to solve".to_string(),
+    }
+}
+
+//length of a game of moves plies after an undo: against the engine back to before the last move of the
+//person in seat human, so the engine's reply goes too, otherwise one ply. None if there is nothing to take back
+fn undo_length(moves: usize, human: Option<usize>) -> Option<usize> {
+    match human {
+        //players[0] makes the even plies
+        Some(human) => (0..moves).rev().find(|&ply| ply % 2 == human),
+        None => moves.checked_sub(1),
+    }
+}
+
+//playing the move that was taken back keeps the rest of the redo history, any other move drops it
+fn forget_redo(redo: &mut Vec<usize>, col: usize) {
+    if redo.last() == Some(&col) {
+        redo.pop();
+    } else {
+        redo.clear();
+    }
+}
+
 impl Connect4 {
     fn render_cell(&self, row: usize, column: usize) -> Html {
-        let red_board = self.bit_board.player_mask;
-        let yellow_board = self.bit_board.total_mask ^ red_board;
+        //the board calls the player moving first red, whichever colour they were given
+        let first_board = self.bit_board.player_mask;
+        let (red_board, yellow_board) = if self.first_red() {
+            (first_board, self.bit_board.total_mask ^ first_board)
+        } else {
+            (self.bit_board.total_mask ^ first_board, first_board)
+        };
         let align = self.bit_board.bottom_col_mask(column) << (HEIGHT - 1 - row);
         let red_elem = red_board & align;
         let yellow_elem = yellow_board & align;
-        let mut coin_class = "";
-
-        if red_elem != 0 && yellow_elem == 0 {
-            coin_class = "red";
+        let coin_class = if red_elem != 0 && yellow_elem == 0 {
+            "red"
         } else if yellow_elem != 0 && red_elem == 0 {
-            coin_class = "yellow";
+            "yellow"
         } else {
-            coin_class = "empty";
-        }
+            "empty"
+        };
 
         html! {
             <div class=("cell", coin_class)></div>
@@ -138,25 +589,45 @@ impl Connect4 {
     }
 
     fn render_turn_message(&self) -> Html {
-        let current_player = match self.bit_board.red_turn {
-            true => "Red's Turn",
-            false => "Yellow's Turn",
+        let turn = self.turn();
+        let current_player = if self.mode != Mode::AgainstEngine {
+            format!("{}'s Turn", self.label(turn))
+        } else if self.players[turn].is_human() {
+            format!("Your Turn ({})", self.colour(turn))
+        } else {
+            format!("Computer's Turn ({})", self.colour(turn))
         };
 
         html!{
             <div class="turn-message">
                 {current_player}
+                { self.render_search_progress() }
             </div>
         }
     }
 
+    fn render_search_progress(&self) -> Html {
+        if self.thinking {
+            let status = self.players[self.turn()].status().unwrap_or("Thinking...".to_string());
+
+            html! {
+                <div class="search-progress">
+                    <div>
+                        { status }
+                    </div>
+                    <button onclick=self.link.callback(|_| Msg::MoveNowClicked)>
+                        {"Move now"}
+                    </button>
+                </div>
+            }
+        } else {
+            html! {}
+        }
+    }
+
     fn render_game_state_message(&self) -> Html {
-        let state_message = match self.bit_board.state {
-            GameState::Win => "You won! :D",
-            GameState::Loss => "You lost! :(",
-            GameState::Tie => "Tie! :|",
-            GameState::Default => "Think carefully!",
-        };
+        let labels = [self.label(0), self.label(1)];
+        let state_message = state_text(self.bit_board.state, self.mode, self.human_first, &labels, self.notice.as_deref());
 
         html! {
             <div class="game-state-message">
@@ -177,20 +648,634 @@ impl Connect4 {
         }
     }
 
+    fn render_history(&self) -> Html {
+        html! {
+            <div class="settings">
+                <button disabled=self.undo_target().is_none() onclick=self.link.callback(|_| Msg::UndoClicked)>
+                    {"Undo"}
+                </button>
+                <button disabled=self.redo.is_empty() || self.game_over onclick=self.link.callback(|_| Msg::RedoClicked)>
+                    {"Redo"}
+                </button>
+            </div>
+        }
+    }
+
+    //hint button while a person is to move, then the suggested column with its reasons
+    fn render_hint(&self) -> Html {
+        if self.game_over || !self.players[self.turn()].is_human() {
+            return html! {};
+        }
+
+        match (&self.hint_search, &self.hint) {
+            (Some(search), _) => html! {
+                <div class="hint">
+                    { format!("Looking for a hint... {}/{} columns", search.completed(), search.total()) }
+                </div>
+            },
+            (None, Some(hint)) => html! {
+                <div class="hint">
+                    { hint.describe() }
+                </div>
+            },
+            (None, None) => html! {
+                <button onclick=self.link.callback(|_| Msg::HintClicked)>
+                    {"Hint"}
+                </button>
+            },
+        }
+    }
+
+    //button after the game, then progress and finally the moves that lost ground
+    fn render_analysis(&self) -> Html {
+        match &self.analysis {
+            None if self.game_over => html! {
+                <button onclick=self.link.callback(|_| Msg::AnalyseClicked)>
+                    {"Analyse game"}
+                </button>
+            },
+            None => html! {},
+            Some(analysis) if !analysis.is_done() => html! {
+                <div class="analysis">
+                    { format!("Analysing move {}/{}...", analysis.completed() + 1, analysis.total()) }
+                </div>
+            },
+            Some(analysis) => {
+                let flagged: Vec<String> = analysis.flagged()
+                    .map(|annotation| annotation.describe_with(&self.label(0), &self.label(1)))
+                    .collect();
+
+                html! {
+                    <div class="analysis">
+                        { if flagged.is_empty() {"No mistakes found among the solved moves"} else {"Moves that changed the result:"} }
+                        <ul>
+                            { for flagged.iter().map(|line| html! { <li>{ line }</li> }) }
+                        </ul>
+                    </div>
+                }
+            }
+        }
+    }
+
+    //who to play against, then colour, opening move and swindling, the players' names or the engines, and the
+    //evaluation overlay. changing anything but a name or the overlay starts the next game right away
+    fn render_settings(&self) -> Html {
+        let mode = match self.mode {
+            Mode::AgainstEngine => "Playing the computer",
+            Mode::HotSeat => "Two players",
+            Mode::Spectator => "Computer against computer",
+        };
+
+        let options = match self.mode {
+            Mode::AgainstEngine => {
+                let colour = if self.human_red {"You play Red"} else {"You play Yellow"};
+                let starter = if self.human_first {"You move first"} else {"Computer moves first"};
+                let swindle = if self.swindle {"Swindle when lost: on"} else {"Swindle when lost: off"};
+
+                html! {
+                    <>
+                        <button onclick=self.link.callback(|_| Msg::ColourClicked)>
+                            { colour }
+                        </button>
+                        <button onclick=self.link.callback(|_| Msg::StarterClicked)>
+                            { starter }
+                        </button>
+                        <button onclick=self.link.callback(|_| Msg::SwindleToggled)>
+                            { swindle }
+                        </button>
+                    </>
+                }
+            }
+            Mode::HotSeat => html! {
+                <>
+                    { self.render_name_input(0) }
+                    { self.render_name_input(1) }
+                </>
+            },
+            Mode::Spectator => html! {
+                <>
+                    { self.render_engine_choice(0) }
+                    { self.render_engine_choice(1) }
+                </>
+            },
+        };
+
+        html! {
+            <div class="settings">
+                <button onclick=self.link.callback(|_| Msg::ModeClicked)>
+                    { mode }
+                </button>
+                { options }
+                <button onclick=self.link.callback(|_| Msg::EvaluationToggled)>
+                    { if self.evaluation.is_some() {"Hide evaluation"} else {"Show evaluation"} }
+                </button>
+            </div>
+        }
+    }
+
+    fn render_engine_choice(&self, seat: usize) -> Html {
+        let engine = self.engines[seat];
+        let level = if engine == Engine::Random {
+            html! {}
+        } else {
+            html! {
+                <button onclick=self.link.callback(move |_| Msg::LevelClicked(seat))>
+                    { LEVELS[self.levels[seat]] }
+                </button>
+            }
+        };
+
+        html! {
+            <>
+                <button onclick=self.link.callback(move |_| Msg::EngineClicked(seat))>
+                    { format!("{}: {}", self.colour(seat), engine.name()) }
+                </button>
+                { level }
+            </>
+        }
+    }
+
+    //speed, pause and step for a spectated game
+    fn render_playback(&self) -> Html {
+        if self.mode != Mode::Spectator {
+            return html! {};
+        }
+
+        let pause = if self.playback.paused {"Resume"} else {"Pause"};
+        let restart = if self.playback.auto_restart {"Auto restart: on"} else {"Auto restart: off"};
+        let step = if self.playback.paused {
+            html! {
+                <button onclick=self.link.callback(|_| Msg::StepClicked)>
+                    {"Step"}
+                </button>
+            }
+        } else {
+            html! {}
+        };
+
+        html! {
+            <div class="settings">
+                <label>
+                    { format!("Delay {} ms", self.delay.as_millis()) }
+                    <input
+                        type="range"
+                        min="0"
+                        max="3000"
+                        step="100"
+                        value=self.delay.as_millis().to_string()
+                        oninput=self.link.callback(|e: InputData| Msg::DelayChanged(e.value.parse().unwrap_or(0)))
+                    />
+                </label>
+                <button onclick=self.link.callback(|_| Msg::PauseClicked)>
+                    { pause }
+                </button>
+                { step }
+                <button onclick=self.link.callback(|_| Msg::AutoRestartToggled)>
+                    { restart }
+                </button>
+            </div>
+        }
+    }
+
+    fn render_name_input(&self, seat: usize) -> Html {
+        html! {
+            <input
+                type="text"
+                placeholder=format!("{} player's name", self.colour(seat))
+                value=self.names[seat].clone()
+                oninput=self.link.callback(move |e: InputData| Msg::NameChanged(seat, e.value))
+            />
+        }
+    }
+
+    //who wins the current position with best play and how quickly, as far as the engine could work it out:
+    //a bar filling up with the colour that is ahead, and a line saying so
+    fn render_evaluation(&self) -> Html {
+        let evaluation = match &self.evaluation {
+            Some(evaluation) if !self.game_over => evaluation,
+            _ => return html! {},
+        };
+
+        let turn = self.turn();
+        let text = evaluation_text(evaluation, &self.label(turn), &self.label(1 - turn));
+
+        //share of the bar that is red, half while nothing is known
+        let advantage = evaluation.advantage().unwrap_or(0.0);
+        let red_advantage = if self.colour(turn) == "Red" {advantage} else {-advantage};
+        let red_percent = 50.0 + 50.0 * red_advantage;
+
+        html! {
+            <div class="evaluation">
+                <div class="evaluation-bar">
+                    <div class="evaluation-red" style=format!("width: {:.1}%", red_percent)></div>
+                    <div class="evaluation-yellow" style=format!("width: {:.1}%", 100.0 - red_percent)></div>
+                </div>
+                { text }
+            </div>
+        }
+    }
+
+    //result of each column for the side to move, lined up above the column buttons
+    fn render_column_scores(&self) -> Html {
+        let evaluation = match &self.evaluation {
+            Some(evaluation) if !self.game_over => evaluation,
+            _ => return html! {},
+        };
+
+        html! {
+            <div class="column-scores">
+                { for (0..WIDTH).map(|col| html! {
+                    <div class="column-score">{ evaluation.describe_column(col) }</div>
+                }) }
+            </div>
+        }
+    }
+
     fn handle_game_state(&mut self, state: GameState) {
         match state {
             GameState::Win | GameState::Loss | GameState::Tie => {
                 self.game_over = true;
                 self.render_game_state_message();
+                self.save_memory();
             }
             GameState::Default => {}
         }
     }
 
+    //hands the engines what they learned on earlier visits, so familiar positions get answered at once
+    fn load_memory(&mut self) {
+        if let Some(bytes) = storage::load(MEMORY_NAME) {
+            for player in self.players.iter_mut().chain(self.bench.iter_mut()) {
+                if let Err(message) = player.load_memory(&bytes) {
+                    self.notice = Some(message);
+                }
+            }
+        }
+    }
+
+    //keeps what the engine facing people learned for the next visit, spectated engines start afresh
+    fn save_memory(&mut self) {
+        let engine = match self.mode {
+            Mode::AgainstEngine => self.players.iter().find(|player| !player.is_human()),
+            _ => self.bench.as_ref(),
+        };
+
+        if let Some(bytes) = engine.and_then(|engine| engine.save_memory(MEMORY_BYTES)) {
+            if let Err(message) = storage::save(MEMORY_NAME, &bytes) {
+                self.notice = Some(message);
+            }
+        }
+    }
+
+    //index into players of the side to move
+    fn turn(&self) -> usize {
+        if self.bit_board.red_turn {0} else {1}
+    }
+
+    //the player moving first has red coins when the person plays red and starts, or plays yellow and does not.
+    //in hot seat and spectated games red always starts
+    fn first_red(&self) -> bool {
+        self.mode != Mode::AgainstEngine || self.human_red == self.human_first
+    }
+
+    //colour of players[seat]
+    fn colour(&self, seat: usize) -> &'static str {
+        if (seat == 0) == self.first_red() {"Red"} else {"Yellow"}
+    }
+
+    //see player_label
+    fn label(&self, seat: usize) -> String {
+        player_label(self.mode, self.colour(seat), &self.names)
+    }
+
+    fn play_move(&mut self, col: usize) {
+        self.stop_turn();
+
+        if self.apply_move(col) {
+            forget_redo(&mut self.redo, col);
+            self.restart_evaluation();
+            self.next_turn();
+        } else {
+            self.notice = Some("Column full, choose another column".to_string());
+        }
+    }
+
+    //puts col on the board, returns false if the column is full
+    fn apply_move(&mut self, col: usize) -> bool {
+        let state = match self.bit_board.play_turn(col) {
+            Ok(state) => state,
+            Err(_) => return false,
+        };
+
+        self.moves.push(col);
+        self.notice = None;
+        self.handle_game_state(state);
+        true
+    }
+
+    //abandons the move being thought about along with anything that was about the position it was for
+    fn stop_turn(&mut self) {
+        self.thinking = false;
+        self.step_task = None;
+        self.time_task = None;
+        self.next_task = None;
+        self.clear_hint();
+    }
+
+    //after moves were taken back or played again: the old analysis no longer fits the game and a
+    //spectated game stops, so it can be stepped through from there
+    fn after_history_change(&mut self) {
+        self.analysis = None;
+        self.analysis_task = None;
+        self.playback.history_changed(self.mode);
+        self.restart_evaluation();
+        self.next_turn();
+    }
+
+    //see undo_length
+    fn undo_target(&self) -> Option<usize> {
+        let human = match self.mode {
+            Mode::AgainstEngine => Some(if self.players[0].is_human() {0} else {1}),
+            _ => None,
+        };
+
+        undo_length(self.moves.len(), human)
+    }
+
+    //moves a spectated game on when its timer fires or Step is clicked, see Playback::advance
+    fn advance(&mut self, stepped: bool) {
+        match self.playback.advance(stepped, self.game_over) {
+            Advance::Move => self.start_turn(),
+            Advance::Restart => self.start_new_game(),
+            Advance::Wait => {}
+        }
+    }
+
+    //lets an engine start on its move, people are waited for until they click
+    fn start_turn(&mut self) {
+        let turn = self.turn();
+
+        if self.game_over || self.players[turn].is_human() {
+            return;
+        }
+
+        let budget = self.move_time(turn);
+        self.players[turn].start_move(&self.bit_board, budget);
+        self.thinking = true;
+        self.time_task = Some(TimeoutService::spawn(budget, self.link.callback(|_| Msg::MoveNowClicked)));
+        self.schedule_think_step();
+    }
+
+    //spectated games wait a moment before each move so they can be followed, and start over when done
+    fn next_turn(&mut self) {
+        if self.mode != Mode::Spectator {
+            self.start_turn();
+            return;
+        }
+
+        self.next_task = None;
+
+        if self.playback.advance(false, self.game_over) == Advance::Wait {
+            return;
+        }
+
+        let delay = if self.game_over {self.delay + RESTART_DELAY} else {self.delay};
+        self.next_task = Some(TimeoutService::spawn(delay, self.link.callback(|_| Msg::NextMove)));
+    }
+
+    //how long players[seat] may think, the solver's level in a spectated game is its time
+    fn move_time(&self, seat: usize) -> Duration {
+        match (self.mode, self.engines[seat]) {
+            (Mode::Spectator, Engine::Solver) => SOLVER_TIMES[self.levels[seat]],
+            _ => MOVE_TIME,
+        }
+    }
+
+    //runs the next thinking step once the browser has had a chance to redraw and handle clicks
+    fn schedule_think_step(&mut self) {
+        let callback = self.link.callback(|_| Msg::ThinkStep);
+        self.step_task = Some(TimeoutService::spawn(Duration::from_millis(0), callback));
+    }
+
+    fn schedule_hint_step(&mut self) {
+        let callback = self.link.callback(|_| Msg::HintStep);
+        self.hint_task = Some(TimeoutService::spawn(Duration::from_millis(0), callback));
+    }
+
+    //a hint only applies to the position it was asked for
+    fn clear_hint(&mut self) {
+        self.hint_search = None;
+        self.hint = None;
+        self.hint_task = None;
+    }
+
+    fn schedule_evaluation_step(&mut self) {
+        let callback = self.link.callback(|_| Msg::EvaluationStep);
+        self.evaluation_task = Some(TimeoutService::spawn(Duration::from_millis(0), callback));
+    }
+
+    //moves the evaluation on to the current position, keeping its tables
+    fn restart_evaluation(&mut self) {
+        self.evaluation_task = None;
+
+        if let Some(evaluation) = self.evaluation.as_mut() {
+            evaluation.set_position(&self.bit_board);
+
+            if !self.game_over {
+                self.schedule_evaluation_step();
+            }
+        }
+    }
+
+    //analysis steps can take a while, so each gets its own turn of the event loop
+    fn schedule_analysis_step(&mut self) {
+        let callback = self.link.callback(|_| Msg::AnalysisStep);
+        self.analysis_task = Some(TimeoutService::spawn(Duration::from_millis(0), callback));
+    }
+
     fn start_new_game(&mut self) {
         // self.array_board = ArrayBoard::new();
+        self.stop_turn();
         self.bit_board = BitBoard::new();
-        self.trans_table = TranspositionTable::new(8388593);
         self.game_over = false;
+        self.moves.clear();
+        self.redo.clear();
+        self.notice = None;
+        self.analysis = None;
+        self.analysis_task = None;
+
+        //the engine facing people sits out other games and comes back afterwards
+        if self.mode != Mode::AgainstEngine && self.bench.is_none() {
+            let seat = if self.players[0].is_human() {1} else {0};
+            self.bench = Some(std::mem::replace(&mut self.players[seat], Box::new(HumanPlayer::new())));
+        }
+
+        match self.mode {
+            Mode::AgainstEngine => {
+                if let Some(engine) = self.bench.take() {
+                    self.players = [Box::new(HumanPlayer::new()), engine];
+                }
+            }
+            Mode::HotSeat => self.players = [Box::new(HumanPlayer::new()), Box::new(HumanPlayer::new())],
+            //the spectated engines are kept from game to game, only picking another replaces one
+            Mode::Spectator => {
+                for seat in 0..2 {
+                    if self.players[seat].is_human() {
+                        self.players[seat] = self.engines[seat].player(self.levels[seat]);
+                    }
+                }
+            }
+        }
+
+        //the engine keeps its table when it changes seats
+        if self.mode == Mode::AgainstEngine && self.players[0].is_human() != self.human_first {
+            self.players.swap(0, 1);
+        }
+
+        for player in self.players.iter_mut() {
+            player.new_game();
+        }
+
+        self.restart_evaluation();
+        self.next_turn();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scripts::tablebase::{Tablebase};
+
+    #[test]
+    fn results_speak_to_the_person() {
+        let labels = ["Red".to_string(), "Yellow".to_string()];
+        assert_eq!(state_text(GameState::Win, Mode::AgainstEngine, true, &labels, None), "You won! :D");
+        assert_eq!(state_text(GameState::Win, Mode::AgainstEngine, false, &labels, None), "You lost! :(");
+        assert_eq!(state_text(GameState::Loss, Mode::AgainstEngine, false, &labels, None), "You won! :D");
+        assert_eq!(state_text(GameState::Tie, Mode::AgainstEngine, true, &labels, None), "Tie! :|");
+    }
+
+    #[test]
+    fn results_between_others_name_the_winner() {
+        let labels = ["Ann (Red)".to_string(), "Bob (Yellow)".to_string()];
+        assert_eq!(state_text(GameState::Win, Mode::HotSeat, true, &labels, None), "Ann (Red) won! :D");
+        assert_eq!(state_text(GameState::Loss, Mode::Spectator, false, &labels, None), "Bob (Yellow) won! :D");
+    }
+
+    #[test]
+    fn notices_show_with_the_state() {
+        let labels = ["Red".to_string(), "Yellow".to_string()];
+        let full = Some("Column full, choose another column");
+        assert_eq!(state_text(GameState::Default, Mode::HotSeat, true, &labels, full), "Column full, choose another column");
+        assert_eq!(state_text(GameState::Tie, Mode::HotSeat, true, &labels, Some("Quota exceeded")), "Tie! :| Quota exceeded");
+    }
+
+    #[test]
+    fn undo_takes_back_the_engine_reply_with_the_move() {
+        //the person moves first and the engine has replied
+        assert_eq!(undo_length(4, Some(0)), Some(2));
+        //the engine's reply is still to come
+        assert_eq!(undo_length(3, Some(0)), Some(2));
+        //the person moves second
+        assert_eq!(undo_length(4, Some(1)), Some(3));
+        assert_eq!(undo_length(1, Some(1)), None);
+        assert_eq!(undo_length(0, Some(0)), None);
+    }
+
+    #[test]
+    fn undo_takes_back_one_move_between_people() {
+        //hot seat and spectated games have no seat to go back to
+        assert_eq!(undo_length(5, None), Some(4));
+        assert_eq!(undo_length(2, None), Some(1));
+        assert_eq!(undo_length(1, None), Some(0));
+        assert_eq!(undo_length(0, None), None);
+    }
+
+    #[test]
+    fn hot_seat_players_go_by_their_names() {
+        let names = ["  Ann ".to_string(), String::new()];
+        assert_eq!(player_label(Mode::HotSeat, "Red", &names), "Ann (Red)");
+        //no name typed in
+        assert_eq!(player_label(Mode::HotSeat, "Yellow", &names), "Yellow");
+        //names only count between people
+        assert_eq!(player_label(Mode::AgainstEngine, "Red", &names), "Red");
+        assert_eq!(player_label(Mode::Spectator, "Red", &names), "Red");
+
+        let labels = [player_label(Mode::HotSeat, "Red", &names), player_label(Mode::HotSeat, "Yellow", &names)];
+        assert_eq!(state_text(GameState::Win, Mode::HotSeat, true, &labels, None), "Ann (Red) won! :D");
+    }
+
+    #[test]
+    fn evaluation_text_names_who_wins() {
+        let tablebase = Tablebase::generate(4, 4);
+        let mut evaluation = PositionEvaluation::new(&BitBoard::with_size(4, 4), AIGame::with_seed(1), 1 << 20);
+        assert_eq!(evaluation_text(&evaluation, "Ann (Red)", "Yellow"), "Evaluating... 0/4 columns");
+
+        for outcome in [Outcome::Win, Outcome::Draw, Outcome::Loss] {
+            let (game, entry) = tablebase.positions().find(|(_, entry)| entry.outcome == outcome).unwrap();
+            evaluation.set_position(&game);
+
+            while !evaluation.step() {}
+
+            //the winner's moves, the winning one included
+            let moves = entry.distance.div_ceil(2);
+            let text = evaluation_text(&evaluation, "Ann (Red)", "Yellow");
+
+            match outcome {
+                Outcome::Win => {
+                    let (col, _) = evaluation.best().unwrap();
+                    assert_eq!(text, format!("Ann (Red) can force a win in {} moves (column {})", moves, col + 1));
+                }
+                Outcome::Draw => assert_eq!(text, "Draw with best play"),
+                Outcome::Loss => assert_eq!(text, format!("Yellow can force a win in {} moves", moves)),
+            }
+        }
+    }
+
+    #[test]
+    fn replaying_keeps_the_redo_history() {
+        //moves taken back, the next one to play again last
+        let mut redo = vec![6, 2, 5];
+        forget_redo(&mut redo, 5);
+        assert_eq!(redo, vec![6, 2]);
+
+        forget_redo(&mut redo, 3);
+        assert!(redo.is_empty());
+    }
+
+    #[test]
+    fn undo_pauses_a_spectated_game() {
+        let mut playback = Playback {paused: false, auto_restart: true};
+        playback.history_changed(Mode::HotSeat);
+        assert!(!playback.paused);
+
+        playback.history_changed(Mode::Spectator);
+        assert!(playback.paused);
+        //the timer no longer moves it on
+        assert_eq!(playback.advance(false, false), Advance::Wait);
+    }
+
+    #[test]
+    fn a_step_plays_one_move() {
+        let playback = Playback {paused: true, auto_restart: false};
+
+        //Step starts one move, once it is played the timer scheduled after it waits for the next Step
+        assert_eq!(playback.advance(true, false), Advance::Move);
+        assert_eq!(playback.advance(false, false), Advance::Wait);
+
+        //stepping past the end starts the next game even without auto restart
+        assert_eq!(playback.advance(true, true), Advance::Restart);
+    }
+
+    #[test]
+    fn running_games_follow_the_timer() {
+        let running = Playback {paused: false, auto_restart: true};
+        assert_eq!(running.advance(false, false), Advance::Move);
+        assert_eq!(running.advance(false, true), Advance::Restart);
+        //Step is only offered while paused
+        assert_eq!(running.advance(true, false), Advance::Wait);
+
+        let single = Playback {paused: false, auto_restart: false};
+        assert_eq!(single.advance(false, true), Advance::Wait);
     }
 }
@@ -1,9 +1,18 @@
+#![recursion_limit = "256"]
+
 mod frontend;
 mod scripts;
+#[cfg(not(target_arch = "wasm32"))]
+mod cli;
 
 use frontend::ui::{Connect4};
 
 fn main() {
+    //natively the binary doubles as a command line tool for engine work
+    #[cfg(not(target_arch = "wasm32"))]
+    if std::env::args().len() > 1 {
+        std::process::exit(cli::commands::run(std::env::args().skip(1).collect()));
+    }
+
     yew::start_app::<Connect4>();
 }
-    
\ No newline at end of file
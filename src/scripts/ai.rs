@@ -1,44 +1,197 @@
-use crate::scripts::bit_board::{BitBoard, GameState};
+use crate::scripts::bit_board::{BitBoard};
 // use crate::scripts::array_board::{ArrayBoard, GameState};
-use crate::scripts::trans_table::{TranspositionTable};
+use crate::scripts::trans_table::{Bound, Entry, Table};
+use crate::scripts::opening_book::{OpeningBook};
+use crate::scripts::pn_search::{PnSearch};
+use crate::scripts::search_control::{CancelToken, Progress, ProgressCallback};
+use crate::scripts::tablebase::{Tablebase};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::{Cell, RefCell};
+use std::sync::Arc;
 
-const HEIGHT: usize = 6;
-const WIDTH: usize = 7;
+const WIDTH: usize = 7; //widest board the engine can play, smaller boards work too
+const SEARCH_DEPTH: i64 = 20;
+const UNLIMITED_DEPTH: i64 = 64; //more plies than any board has cells, so the search only ends with the game
+const PROGRESS_INTERVAL: u64 = 1 << 16; //nodes between two progress reports
+const STEP_NODES: u64 = 1 << 17; //nodes a search_step may spend before handing control back
+const PN_MAX_NODES: usize = 4_000_000; //tree size at which proof-number search gives up, 20 bytes per node
+
+//theoretical result of a position for the player to move
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Outcome {
+    Win,
+    Draw,
+    Loss,
+}
+
+impl Outcome {
+    pub fn from_score(score: i64) -> Self {
+        if score > 0 {
+            Outcome::Win
+        } else if score < 0 {
+            Outcome::Loss
+        } else {
+            Outcome::Draw
+        }
+    }
+}
+
+//a move search that can be advanced one root column at a time, see AIGame::start_search
+pub struct RootSearch {
+    pending: Vec<usize>, //columns still to be scored, in search order
+    scores: [Option<i64>; WIDTH], //scores of the finished columns
+    decided: Option<usize>, //move chosen without a search (book move, immediate or weak win)
+    total: usize, //number of columns to score when the search started
+    step_nodes: u64, //node budget of the next step, doubled while the same column keeps running out
+    replies: Vec<(usize, usize)>, //swindle: (losing column, opponent reply) pairs still to be searched
+    punishers: [Option<(bool, usize)>; WIDTH], //swindle: immediate win and winning replies found so far per losing column
+}
+
+impl RootSearch {
+    pub fn is_done(&self) -> bool {
+        self.decided.is_some() || (self.pending.is_empty() && self.replies.is_empty())
+    }
+
+    pub fn completed(&self) -> usize {
+        self.total - self.pending.len()
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    //scores of the columns finished so far, None for the others
+    pub fn scores(&self) -> &[Option<i64>] {
+        &self.scores
+    }
+}
 
 pub struct AIGame {
-    column_order: [i64; WIDTH],
+    order_offset: usize, //rotation of the centre first column order, see staggered
     pub debug: String,
     pub bound: usize,
+    pub weak: bool, //only tell wins, draws and losses apart, so chosen_move picks any winning move
+    pub tolerance: i64, //chosen_move also considers moves this far below the best score, never changing the outcome
+    pub swindle: bool, //when every move loses, prefer the ones that are hardest for the opponent to punish
+    seed: u64, //seed of rng, reported so games can be replayed
+    rng: StdRng, //breaks ties between equally good moves
+    book: Arc<OpeningBook>, //shared between engines, see OpeningBook::shared
+    tablebase: Option<Arc<Tablebase>>, //consulted before any search on boards it covers
+    cancel: CancelToken, //checked at every node, a cancelled search plays the best move found so far
+    progress: Cell<Progress>,
+    search_depth: Cell<i64>, //depth the running negamax started from, for progress reports
+    step_end: Cell<u64>, //node count at which the running search_step stops, u64::MAX outside of steps
+    on_progress: RefCell<Option<ProgressCallback>>,
 }
 
 impl AIGame {
     pub fn new() -> Self {
-            let mut column_order = [0; WIDTH];
-
-            for i in 0..WIDTH {
-                column_order[i] = WIDTH as i64 / 2 + (1 - 2 * (i as i64 % 2)) * (i as i64 + 1) / 2;
-            }
+        Self::with_seed(rand::random())
+    }
 
+    pub fn with_seed(seed: u64) -> Self {
             AIGame {
-                column_order,
+                order_offset: 0,
                 debug: String::new(),
                 bound: 0,
+                weak: false,
+                tolerance: 0,
+                swindle: false,
+                seed,
+                rng: StdRng::seed_from_u64(seed),
+                book: OpeningBook::shared(),
+                tablebase: None,
+                cancel: CancelToken::new(),
+                progress: Cell::new(Progress::default()),
+                search_depth: Cell::new(SEARCH_DEPTH),
+                step_end: Cell::new(u64::MAX),
+                on_progress: RefCell::new(None),
             }
     }
 
-    pub fn make_move(&mut self, game: &mut BitBoard, trans_table: &mut TranspositionTable) -> Result<GameState, String> {
-        let mut best_move: usize = 0;
-        let mut best_score: i64 = std::i64::MIN;
-        let mut depth: i64 = 20;
+    pub fn seed(&self) -> u64 {
+        return self.seed;
+    }
+
+    //helper for parallel search: rotates the move order so threads explore the tree differently
+    #[cfg(feature = "parallel")]
+    pub fn staggered(offset: usize, cancel: CancelToken) -> Self {
+        let mut ai = Self::new();
+        ai.order_offset = offset;
+        ai.cancel = cancel;
+        ai
+    }
+
+    pub fn set_tablebase(&mut self, tablebase: Arc<Tablebase>) {
+        self.tablebase = Some(tablebase);
+    }
+
+    //token that stops the current or next search when cancelled,
+    //a cancelled token stays cancelled so set a new one before searching again
+    pub fn set_cancel_token(&mut self, cancel: CancelToken) {
+        self.cancel = cancel;
+    }
+
+    pub fn set_progress_callback(&mut self, callback: ProgressCallback) {
+        self.on_progress = RefCell::new(Some(callback));
+    }
+
+    //state of the running or last finished search
+    pub fn progress(&self) -> Progress {
+        self.progress.get()
+    }
+
+    fn report_progress(&self) {
+        if let Some(callback) = self.on_progress.borrow_mut().as_mut() {
+            callback(&self.progress.get());
+        }
+    }
+
+    //cancelled, or the running search_step used up its nodes
+    fn stopped(&self) -> bool {
+        self.cancel.is_cancelled() || self.progress.get().nodes >= self.step_end.get()
+    }
+
+    fn update_progress<F: FnOnce(&mut Progress)>(&self, update: F) {
+        let mut progress = self.progress.get();
+        update(&mut progress);
+        self.progress.set(progress);
+    }
+
+    //prepares a move search without doing any work yet, so callers like the web UI can
+    //spread it over several frames with search_step and stop early with chosen_move
+    pub fn start_search(&mut self, game: &BitBoard) -> RootSearch {
         self.debug = String::new();
         self.bound = game.get_num_moves();
+        self.progress.set(Progress::default());
         // self.debug.push_str(&game.total_mask.to_string());
         // self.debug.push_str(&format!("{:b}", game.total_mask));
 
-        if game.total_mask == 400556032 {        
-            return game.play_turn(5);
-        } else if game.total_mask == 35297165312 {
-            return game.play_turn(4);
+        let mut root = RootSearch {
+            pending: Vec::new(),
+            scores: [None; WIDTH],
+            decided: None,
+            total: 0,
+            step_nodes: STEP_NODES,
+            replies: Vec::new(),
+            punishers: [None; WIDTH],
+        };
+
+        if let Some(scores) = self.tablebase_scores(game) {
+            for (col, score) in scores.iter().enumerate() {
+                if let Some(score) = *score {
+                    root.scores[col] = Some(score);
+                    self.record_root_score(col, score);
+                }
+            }
+
+            return root;
+        }
+
+        if let Some(col) = self.book.get_move(game) {
+            root.decided = Some(col);
+            return root;
         }
 
         // if self.bound <= 6 {
@@ -49,46 +202,403 @@ impl AIGame {
         //     trans_table.reset();
         // }
 
-        for col in 0..WIDTH {
-            let chosen_col: usize = self.column_order[col].try_into().unwrap(); 
+        let wins: Vec<usize> = (0..game.width())
+            .filter(|&col| game.is_move_valid(col) && game.is_winning_move(col))
+            .collect();
+
+        //all immediate wins share the best score, no need to search
+        if !wins.is_empty() {
+            root.decided = Some(wins[self.rng.gen_range(0..wins.len())]);
+            return root;
+        }
+
+        for col in 0..game.width() {
+            let chosen_col = self.column_order(game.width(), col);
+
+            if game.is_move_valid(chosen_col) {
+                root.pending.push(chosen_col);
+            }
+        }
+
+        root.total = root.pending.len();
+        return root;
+    }
+
+    //works on the next root column for a bounded number of nodes, so callers without threads
+    //get control back regularly. returns true once the search is complete or cancelled
+    pub fn search_step<T: Table>(&mut self, root: &mut RootSearch, game: &mut BitBoard, trans_table: &mut T) -> bool {
+        if root.is_done() || self.cancel.is_cancelled() {
+            return true;
+        }
+
+        if root.pending.is_empty() {
+            return self.swindle_step(root, game, trans_table);
+        }
+
+        let col = root.pending[0];
+        self.update_progress(|progress| progress.root_move = Some(col));
+        self.report_progress();
+        self.step_end.set(self.progress.get().nodes + root.step_nodes);
+        let score = self.score_move(game, trans_table, col);
+        let stopped = self.stopped();
+        self.step_end.set(u64::MAX);
+        // self.debug.push_str(&format!("(col:{}, score:{}) ", (6 - col).to_string(), score.to_string()));
+
+        //the score of a column interrupted halfway is meaningless
+        if self.cancel.is_cancelled() {
+            return true;
+        }
+
+        //out of nodes: the table keeps the finished subtrees, so the next step gets further with a larger budget
+        if stopped {
+            root.step_nodes *= 2;
+            return false;
+        }
+
+        root.pending.remove(0);
+        root.step_nodes = STEP_NODES;
+        root.scores[col] = Some(score);
+        self.record_root_score(col, score);
+
+        //in weak mode every win is as good as the fastest one
+        if self.weak && score > 0 {
+            root.decided = Some(col);
+        }
+
+        if self.swindle && root.pending.is_empty() && root.decided.is_none() {
+            self.start_swindle(root, game);
+        }
+
+        return root.is_done();
+    }
+
+    //in a lost position every losing column gets its opponent replies searched by later steps,
+    //columns allowing an immediate win need no search. nothing to do if some column does not lose
+    fn start_swindle(&self, root: &mut RootSearch, game: &mut BitBoard) {
+        if root.scores.iter().flatten().any(|&score| score >= 0) {
+            return;
+        }
+
+        for col in 0..game.width() {
+            if root.scores[col].is_none() {
+                continue;
+            }
+
+            game.play_move(col);
+            let replies: Vec<usize> = (0..game.width()).filter(|&reply| game.is_move_valid(reply)).collect();
+
+            if replies.iter().any(|&reply| game.is_winning_move(reply)) {
+                root.punishers[col] = Some((true, replies.len()));
+            } else {
+                root.punishers[col] = Some((false, 0));
+                root.replies.extend(replies.iter().map(|&reply| (col, reply)));
+            }
+
+            let _ = game.undo_move(col);
+        }
+
+        if root.replies.is_empty() {
+            root.decided = swindle_move(&root.punishers, &root.scores);
+        }
+    }
+
+    //searches the next opponent reply of a swindle with the same node budget as the root columns
+    fn swindle_step<T: Table>(&mut self, root: &mut RootSearch, game: &mut BitBoard, trans_table: &mut T) -> bool {
+        let (col, reply) = root.replies[0];
+        self.step_end.set(self.progress.get().nodes + root.step_nodes);
+        game.play_move(col);
+        //null window searches only tell whether the reply keeps the opponent's win
+        let score = self.score_move_within(game, trans_table, reply, 1);
+        let _ = game.undo_move(col);
+        let stopped = self.stopped();
+        self.step_end.set(u64::MAX);
+
+        if self.cancel.is_cancelled() {
+            return true;
+        }
+
+        if stopped {
+            root.step_nodes *= 2;
+            return false;
+        }
+
+        root.replies.remove(0);
+        root.step_nodes = STEP_NODES;
+
+        if score > 0 {
+            if let Some((_, winning)) = root.punishers[col].as_mut() {
+                *winning += 1;
+            }
+        }
+
+        if root.replies.is_empty() {
+            root.decided = swindle_move(&root.punishers, &root.scores);
+        }
+
+        return root.is_done();
+    }
+
+    //column to play, using whatever columns have been scored if the search was cut short
+    pub fn chosen_move(&mut self, root: &RootSearch) -> usize {
+        match root.decided {
+            Some(col) => col,
+            None => self.pick_move(&root.scores).unwrap_or_else(|| {
+                //nothing finished in time, fall back to the first column in search order
+                root.pending.first().copied().unwrap_or(0)
+            }),
+        }
+    }
+
+    fn record_root_score(&self, col: usize, score: i64) {
+        self.update_progress(|progress| {
+            if progress.best_score.is_none_or(|best| score > best) {
+                progress.best_move = Some(col);
+                progress.best_score = Some(score);
+            }
+        });
+        self.report_progress();
+    }
+
+    //finds the best column for the player to move along with its score, without playing it
+    pub fn search<T: Table>(&self, game: &mut BitBoard, trans_table: &mut T) -> (usize, i64) {
+        let mut best_move: usize = 0;
+        let mut best_score: i64 = i64::MIN;
+        self.progress.set(Progress::default());
+
+        if let Some(tablebase) = &self.tablebase {
+            if let (Some(entry), Some(col)) = (tablebase.get(game), tablebase.best_move(game)) {
+                return (col, entry.score(game));
+            }
+        }
+
+        for col in 0..game.width() {
+            let chosen_col = self.column_order(game.width(), col);
 
             if game.is_move_valid(chosen_col) {
                 if game.is_winning_move(chosen_col) {
-                    return game.play_turn(chosen_col);
+                    return (chosen_col, ((cells(game) + 1 - game.get_num_moves()) / 2) as i64);
                 }
 
-                let init:i64 = ((WIDTH * HEIGHT + 1 - game.get_num_moves()) / 2) as i64;
-                game.play_move(chosen_col);
-                let score = -self.negamax(game, trans_table, -init, init, depth);
-                // self.debug.push_str(&format!("col:{} |", chosen_col.to_string()));
+                self.update_progress(|progress| progress.root_move = Some(chosen_col));
+                let score = self.score_move(game, trans_table, chosen_col);
 
-                // self.debug.push_str(&format!("(col:{}, score:{}) ", (6 - chosen_col).to_string(), score.to_string()));
-                let _ = game.undo_move(chosen_col);
+                if self.cancel.is_cancelled() {
+                    break;
+                }
+
+                self.record_root_score(chosen_col, score);
+
+                //in weak mode every win is as good as the fastest one
+                if self.weak && score > 0 {
+                    return (chosen_col, score);
+                }
 
                 if score > best_score {
                     best_move = chosen_col;
-                    // self.debug.push_str(&format!("best:{} |", best_move.to_string()));
                     best_score = score;
                 }
             }
         }
-        
-        // self.debug.push_str(&format!("best:{} |", best_move.to_string()));
-        return game.play_turn(best_move);
+
+        //cancelled before any column finished
+        if best_score == i64::MIN {
+            best_move = (0..game.width()).find(|&col| game.is_move_valid(col)).unwrap_or(0);
+        }
+
+        return (best_move, best_score);
+    }
+
+    //exact score of every valid column when the tablebase covers the position
+    fn tablebase_scores(&self, game: &BitBoard) -> Option<[Option<i64>; WIDTH]> {
+        let tablebase = self.tablebase.as_ref()?;
+        let mut game = game.clone();
+        let mut scores = [None; WIDTH];
+        tablebase.get(&game)?;
+
+        for (col, score) in scores.iter_mut().enumerate().take(game.width()) {
+            if game.is_move_valid(col) {
+                *score = Some(tablebase.move_score(&mut game, col)?);
+            }
+        }
+
+        return Some(scores);
+    }
+
+    //random choice among the best columns, or those within tolerance of the best with the same outcome
+    fn pick_move(&mut self, scores: &[Option<i64>; WIDTH]) -> Option<usize> {
+        let best_score = scores.iter().flatten().copied().max()?;
+        let candidates: Vec<usize> = (0..WIDTH)
+            .filter(|&col| match scores[col] {
+                Some(score) => score >= best_score - self.tolerance
+                    && Outcome::from_score(score) == Outcome::from_score(best_score),
+                None => false,
+            })
+            .collect();
+
+        return Some(candidates[self.rng.gen_range(0..candidates.len())]);
+    }
+
+    //score of playing a column from the point of view of the player to move
+    pub fn score_move<T: Table>(&self, game: &mut BitBoard, trans_table: &mut T, col: usize) -> i64 {
+        let init:i64 = if self.weak {1} else {((cells(game) + 1 - game.get_num_moves()) / 2) as i64};
+        return self.score_move_within(game, trans_table, col, init);
+    }
+
+    //score of a column searched with the window [-init, init]
+    fn score_move_within<T: Table>(&self, game: &mut BitBoard, trans_table: &mut T, col: usize, init: i64) -> i64 {
+        if game.is_winning_move(col) {
+            return ((cells(game) + 1 - game.get_num_moves()) / 2) as i64;
+        }
+
+        //a win, draw or loss can only be told apart with certainty by searching to the end of the game,
+        //the horizon would otherwise count every unfinished line as a win
+        let depth = if init == 1 {UNLIMITED_DEPTH} else {SEARCH_DEPTH};
+        self.search_depth.set(depth);
+        game.play_move(col);
+        let score = -self.negamax(game, trans_table, -init, init, depth);
+        let _ = game.undo_move(col);
+
+        return score;
+    }
+
+    //exact best column and score without a depth limit, for the opening book: the score is narrowed down
+    //with null window searches, then the first column in search order reaching it is the best.
+    //far slower than search early in the game
+    pub fn solve<T: Table>(&self, game: &mut BitBoard, trans_table: &mut T) -> (usize, i64) {
+        let cells = cells(game);
+        let n = game.get_num_moves();
+        let valid: Vec<usize> = (0..game.width())
+            .map(|i| self.column_order(game.width(), i))
+            .filter(|&col| game.is_move_valid(col))
+            .collect();
+
+        if let Some(&col) = valid.iter().find(|&&col| game.is_winning_move(col)) {
+            return (col, ((cells + 1 - n) / 2) as i64);
+        }
+
+        self.search_depth.set(UNLIMITED_DEPTH);
+        self.progress.set(Progress::default());
+        let mut min = -(((cells - n) / 2) as i64);
+        let mut max = ((cells + 1 - n) / 2) as i64;
+
+        while min < max && !self.cancel.is_cancelled() {
+            //probing closer to zero first pays off, most early positions are close
+            let mut med = min + (max - min) / 2;
+
+            if med <= 0 && min / 2 < med {
+                med = min / 2;
+            } else if med >= 0 && max / 2 > med {
+                med = max / 2;
+            }
+
+            let score = self.negamax(game, trans_table, med, med + 1, UNLIMITED_DEPTH);
+
+            if score <= med {
+                max = score;
+            } else {
+                min = score;
+            }
+        }
+
+        //a column reaches the score when the opponent cannot keep its reply below it
+        for &col in valid.iter() {
+            game.play_move(col);
+            let reply = self.negamax(game, trans_table, -min, -min + 1, UNLIMITED_DEPTH);
+            let _ = game.undo_move(col);
+
+            if -reply >= min {
+                return (col, min);
+            }
+        }
+
+        return (valid.first().copied().unwrap_or(0), min);
+    }
+
+    //true when search and score_move give exact scores for the position: the tablebase covers it,
+    //or the end of the game is within reach of the depth limit
+    pub fn is_exact(&self, game: &BitBoard) -> bool {
+        if self.tablebase.as_ref().is_some_and(|tablebase| tablebase.get(game).is_some()) {
+            return true;
+        }
+
+        return game.get_num_moves() + SEARCH_DEPTH as usize + 2 >= cells(game);
+    }
+
+    //classifies the position for the player to move with the null window [-1, 1],
+    //much cheaper than finding the exact score
+    pub fn weak_solve<T: Table>(&self, game: &mut BitBoard, trans_table: &mut T) -> Outcome {
+        if let Some(entry) = self.tablebase.as_ref().and_then(|tablebase| tablebase.get(game)) {
+            return entry.outcome;
+        }
+
+        let mut best = Outcome::Loss;
+
+        for col in 0..game.width() {
+            let chosen_col = self.column_order(game.width(), col);
+
+            if game.is_move_valid(chosen_col) {
+                match Outcome::from_score(self.score_move_within(game, trans_table, chosen_col, 1)) {
+                    Outcome::Win => return Outcome::Win,
+                    Outcome::Draw => best = Outcome::Draw,
+                    Outcome::Loss => {}
+                }
+            }
+        }
+
+        return best;
+    }
+
+    //exact result without a depth limit using proof-number search, None if it ran out of nodes or was cancelled,
+    //the table keeps solved positions and should not be shared with negamax
+    pub fn pn_solve<T: Table>(&self, game: &BitBoard, trans_table: &mut T) -> Option<Outcome> {
+        return self.pn_solve_within(game, trans_table, PN_MAX_NODES);
+    }
+
+    //pn_solve giving up once the proof tree holds max_nodes nodes
+    pub fn pn_solve_within<T: Table>(&self, game: &BitBoard, trans_table: &mut T, max_nodes: usize) -> Option<Outcome> {
+        return PnSearch::new(trans_table, &self.cancel, max_nodes).solve(game);
     }
 
-    pub fn negamax(&self, game: &mut BitBoard, trans_table: &mut TranspositionTable, mut alpha: i64, mut beta: i64, depth: i64) -> i64 {
-        if game.get_num_moves() >= WIDTH * HEIGHT - 2 {
+    pub fn negamax<T: Table>(&self, game: &mut BitBoard, trans_table: &mut T, mut alpha: i64, mut beta: i64, depth: i64) -> i64 {
+        if self.stopped() {
             return 0;
-        } 
+        }
+
+        let alpha_start = alpha; //a result above it is exact, anything else only an upper bound
 
-        for col in 0..WIDTH {
+        let nodes = self.progress.get().nodes + 1;
+        let ply = (self.search_depth.get() - depth + 1).max(0) as usize;
+        self.update_progress(|progress| {
+            progress.nodes = nodes;
+            progress.depth = progress.depth.max(ply);
+        });
+
+        if nodes.is_multiple_of(PROGRESS_INTERVAL) {
+            self.report_progress();
+        }
+
+        let cells = cells(game);
+
+        for col in 0..game.width() {
             if game.is_move_valid(col) && game.is_winning_move(col) {
-                return ((WIDTH * HEIGHT + 1 - game.get_num_moves()) / 2) as i64;
+                return ((cells + 1 - game.get_num_moves()) / 2) as i64;
             }
         }
 
-        let min = -(((WIDTH * HEIGHT - 2 - game.get_num_moves()) / 2) as i64);
+        let next = game.non_losing_moves();
+
+        //whatever is played, the opponent wins with their next coin
+        if next == 0 {
+            return -(((cells - game.get_num_moves()) / 2) as i64);
+        }
+
+        //the last two coins cannot win once neither side can win right away
+        if game.get_num_moves() >= cells - 2 {
+            return 0;
+        } 
+
+        //the opponent can win at the earliest with their second coin from here
+        let min = -(((cells - 2 - game.get_num_moves()) / 2) as i64);
 
         if alpha < min {
             alpha = min;                     
@@ -98,14 +608,32 @@ impl AIGame {
             }
         }
 
-        let mut max = (WIDTH * HEIGHT - 1 - game.get_num_moves()) as i64 / 2;
-        let val = trans_table.get(game.get_unique_key());
-        if val != 0 {
-            max = val as i64 - (WIDTH * HEIGHT / 2) as i64 + 3;
+        let mut max = (cells - 1 - game.get_num_moves()) as i64 / 2;
+        let key = game.get_unique_key();
+        let entry = trans_table.get(key);
+        //past the end of the game the depth limit makes no difference
+        let reach = depth.min((cells - game.get_num_moves()) as i64) as u8;
+
+        //only a search that looked at least as far ahead can stand in for this one,
+        //but any entry's best move is worth trying first
+        if let Some(entry) = entry.filter(|entry| entry.depth >= reach) {
+            match entry.bound {
+                Bound::Exact => return entry.value,
+                Bound::Lower => {
+                    if entry.value > alpha {
+                        alpha = entry.value;
+
+                        if alpha >= beta {
+                            return alpha;
+                        }
+                    }
+                }
+                Bound::Upper => max = max.min(entry.value),
+            }
         }
 
-        if beta > max.try_into().unwrap() {
-            beta = max.try_into().unwrap();
+        if beta > max {
+            beta = max;
             
             if alpha >= beta {
                 return beta;
@@ -114,28 +642,339 @@ impl AIGame {
         
         if depth == 0 {
             // return alpha;
-            return ((WIDTH * HEIGHT + 1 - game.get_num_moves()) / 2) as i64;
+            return ((cells + 1 - game.get_num_moves()) / 2) as i64;
         }
 
-        for col in 0..WIDTH {
-            let chosen_col = self.column_order[col].try_into().unwrap(); 
+        let (moves, count) = self.ordered_moves(game, next, entry.and_then(|entry| entry.best_move));
+        let mut best_move = None;
 
-            if game.is_move_valid(chosen_col) {
-                game.play_move(chosen_col);
-                let score = -self.negamax(game, trans_table, -beta, -alpha, depth - 1);
-                let _ = game.undo_move(chosen_col);
+        for &chosen_col in &moves[..count] {
+            game.play_move(chosen_col);
+            let score = -self.negamax(game, trans_table, -beta, -alpha, depth - 1);
+            let _ = game.undo_move(chosen_col);
 
-                if score >= beta {
-                    return score;
-                }   
+            //an aborted search returns garbage scores which must not reach the table
+            if self.stopped() {
+                return alpha;
+            }
 
-                if score > alpha {
-                    alpha = score;
-                }
+            if score >= beta {
+                trans_table.insert(key, Entry {value: score, bound: Bound::Lower, best_move: Some(chosen_col), depth: reach});
+                return score;
+            }   
+
+            if score > alpha {
+                alpha = score;
+                best_move = Some(chosen_col);
             }
         }
 
-        trans_table.insert(game.get_unique_key(), (alpha + (WIDTH * HEIGHT / 2) as i64 - 3) as u64);
+        let bound = if alpha > alpha_start {Bound::Exact} else {Bound::Upper};
+        trans_table.insert(key, Entry {value: alpha, bound, best_move, depth: reach});
         return alpha;
     }
-}
\ No newline at end of file
+
+    //i-th column to search on a board of the given width, centre columns first
+    fn column_order(&self, width: usize, i: usize) -> usize {
+        let i = (i + self.order_offset) % width;
+        return (width as i64 / 2 + (1 - 2 * (i as i64 % 2)) * (i as i64 + 1) / 2) as usize;
+    }
+
+    //columns landing in allowed sorted by the number of winning cells they create for the player to move,
+    //ties keep the centre first column order. first, the best move remembered by the table, goes ahead of all
+    fn ordered_moves(&self, game: &BitBoard, allowed: u64, first: Option<usize>) -> ([usize; WIDTH], usize) {
+        let mut moves = [0; WIDTH];
+        let mut scores = [0; WIDTH];
+        let mut count = 0;
+
+        for i in 0..game.width() {
+            let col = self.column_order(game.width(), i);
+
+            if game.is_move_valid(col) && game.move_mask(col) & allowed != 0 {
+                let score = game.move_score(col);
+                let mut pos = count;
+
+                //insertion sort, moving only past strictly smaller scores
+                while pos > 0 && scores[pos - 1] < score {
+                    moves[pos] = moves[pos - 1];
+                    scores[pos] = scores[pos - 1];
+                    pos -= 1;
+                }
+
+                moves[pos] = col;
+                scores[pos] = score;
+                count += 1;
+            }
+        }
+
+        if let Some(pos) = first.and_then(|first| moves[..count].iter().position(|&col| col == first)) {
+            moves[..=pos].rotate_right(1);
+        }
+
+        return (moves, count);
+    }
+}
+
+//the losing column giving the opponent the hardest time: no immediate win for them,
+//as few winning replies as possible, then the slowest loss
+fn swindle_move(punishers: &[Option<(bool, usize)>; WIDTH], scores: &[Option<i64>; WIDTH]) -> Option<usize> {
+    let mut best: Option<((bool, usize, i64), usize)> = None;
+
+    for col in 0..WIDTH {
+        if let (Some((immediate, winning)), Some(score)) = (punishers[col], scores[col]) {
+            let key = (immediate, winning, -score);
+
+            if best.is_none_or(|(best_key, _)| key < best_key) {
+                best = Some((key, col));
+            }
+        }
+    }
+
+    return best.map(|(_, col)| col);
+}
+
+//number of cells on the board
+fn cells(game: &BitBoard) -> usize {
+    return game.width() * game.height();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scripts::trans_table::{TranspositionTable};
+
+    //remembers the positions the search looked up, in order
+    struct Recorder {
+        table: TranspositionTable,
+        lookups: RefCell<Vec<u64>>,
+    }
+
+    impl Table for Recorder {
+        fn get(&self, key: u64) -> Option<Entry> {
+            self.lookups.borrow_mut().push(key);
+            self.table.get(key)
+        }
+
+        fn insert(&mut self, key: u64, entry: Entry) {
+            self.table.insert(key, entry)
+        }
+    }
+
+    fn play(game: &mut BitBoard, line: &str) {
+        for c in line.chars() {
+            game.play_move(c.to_digit(10).unwrap() as usize);
+        }
+    }
+
+    #[test]
+    fn weak_solve_matches_the_tablebase() {
+        let tablebase = Tablebase::generate(4, 4);
+        let ai = AIGame::with_seed(1);
+        let mut trans_table = TranspositionTable::with_budget(1 << 20);
+
+        for (mut game, entry) in tablebase.positions().step_by(7) {
+            assert_eq!(ai.weak_solve(&mut game, &mut trans_table), entry.outcome, "key {}", game.get_unique_key());
+        }
+    }
+
+    #[test]
+    fn weak_solve_looks_past_the_depth_limit() {
+        let ai = AIGame::with_seed(1);
+
+        //draws with more than SEARCH_DEPTH cells left, the horizon used to call them losses
+        for line in ["42230230036642463", "631532064664620533"] {
+            let mut game = BitBoard::new();
+            play(&mut game, line);
+            assert!(cells(&game) - game.get_num_moves() > SEARCH_DEPTH as usize);
+            assert_eq!(ai.weak_solve(&mut game, &mut TranspositionTable::with_budget(1 << 20)), Outcome::Draw, "{}", line);
+            assert_eq!(ai.pn_solve(&game, &mut TranspositionTable::with_budget(1 << 20)), Some(Outcome::Draw), "{}", line);
+        }
+    }
+
+    #[test]
+    fn fixed_seed_breaks_ties_the_same_way() {
+        let scores = [Some(3), None, Some(3), Some(2), Some(3), Some(-1), Some(3)];
+        let picks = |seed: u64| {
+            let mut ai = AIGame::with_seed(seed);
+            (0..50).map(|_| ai.pick_move(&scores).unwrap()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(picks(7), picks(7));
+        assert!(picks(7).iter().all(|&col| scores[col] == Some(3)));
+        //every best column gets picked sooner or later
+        assert!([0, 2, 4, 6].iter().all(|col| picks(7).contains(col)));
+    }
+
+    #[test]
+    fn tolerance_keeps_the_outcome() {
+        let scores = [Some(3), Some(2), Some(0), None, None, None, None];
+        let mut ai = AIGame::with_seed(1);
+        ai.tolerance = 5;
+
+        //the drawing column is never traded for a win
+        let picks: Vec<usize> = (0..50).map(|_| ai.pick_move(&scores).unwrap()).collect();
+        assert!(picks.contains(&1));
+        assert!(!picks.contains(&2));
+    }
+
+    #[test]
+    fn search_steps_are_bounded_and_resume() {
+        let mut game = BitBoard::new();
+        play(&mut game, "512134654565");
+        let mut ai = AIGame::with_seed(1);
+        let mut trans_table = TranspositionTable::with_budget(16 << 20);
+        let mut root = ai.start_search(&game);
+        let mut retries = 0;
+
+        loop {
+            let (completed, nodes) = (root.completed(), ai.progress().nodes);
+            let budget = root.step_nodes;
+            let done = ai.search_step(&mut root, &mut game, &mut trans_table);
+            assert!(ai.progress().nodes - nodes <= budget);
+
+            if done {
+                break;
+            }
+
+            if root.completed() == completed {
+                retries += 1;
+            }
+        }
+
+        //a column cut short is searched again, and ends with the score of an uninterrupted search
+        assert!(retries > 0);
+
+        for col in 0..game.width() {
+            let score = ai.score_move(&mut game, &mut TranspositionTable::with_budget(16 << 20), col);
+            assert_eq!(root.scores()[col], Some(score), "column {}", col);
+        }
+    }
+
+    #[test]
+    fn swindles_are_searched_in_bounded_steps() {
+        let tablebase = Tablebase::generate(5, 4);
+        let lost = tablebase.positions()
+            .filter(|(game, entry)| entry.outcome == Outcome::Loss && game.get_num_moves() < 10)
+            .step_by(11)
+            .take(6);
+
+        for (mut game, _) in lost {
+            let mut ai = AIGame::with_seed(1);
+            ai.swindle = true;
+            let mut trans_table = TranspositionTable::with_budget(1 << 20);
+            let mut root = ai.start_search(&game);
+            let mut steps = 0;
+
+            loop {
+                let (nodes, budget) = (ai.progress().nodes, root.step_nodes);
+                let done = ai.search_step(&mut root, &mut game, &mut trans_table);
+                assert!(ai.progress().nodes - nodes <= budget);
+                steps += 1;
+
+                if done {
+                    break;
+                }
+            }
+
+            //one step per column and per reply of a losing column at most, none of them ran out of nodes here
+            assert!(steps <= game.width() * (game.width() + 1), "key {}", game.get_unique_key());
+
+            //the fewest winning replies according to the tablebase, immediate wins counting as every reply
+            let mut best: Option<((bool, usize, i64), usize)> = None;
+
+            let valid: Vec<usize> = (0..game.width()).filter(|&col| game.is_move_valid(col)).collect();
+
+            for col in valid {
+                let score = tablebase.move_score(&mut game, col).unwrap();
+                game.play_move(col);
+                let replies: Vec<usize> = (0..game.width()).filter(|&reply| game.is_move_valid(reply)).collect();
+                let immediate = replies.iter().any(|&reply| game.is_winning_move(reply));
+                let winning = if immediate {
+                    replies.len()
+                } else {
+                    replies.iter().filter(|&&reply| tablebase.move_score(&mut game, reply).unwrap() > 0).count()
+                };
+                let _ = game.undo_move(col);
+                let key = (immediate, winning, -score);
+
+                if best.is_none_or(|(best_key, _)| key < best_key) {
+                    best = Some((key, col));
+                }
+            }
+
+            assert_eq!(ai.chosen_move(&root), best.unwrap().1, "key {}", game.get_unique_key());
+        }
+    }
+
+    #[test]
+    fn threats_are_searched_first() {
+        let mut game = BitBoard::new();
+        play(&mut game, "2636");
+        let ai = AIGame::with_seed(1);
+
+        //open threes first, then broken ones, ties in centre first order
+        assert_eq!(ai.ordered_moves(&game, u64::MAX, None), ([4, 1, 5, 0, 3, 2, 6], 7));
+        //the table's best move goes ahead of all of them
+        assert_eq!(ai.ordered_moves(&game, u64::MAX, Some(6)), ([6, 4, 1, 5, 0, 3, 2], 7));
+        //moves outside allowed are left out
+        assert_eq!(ai.ordered_moves(&game, game.move_mask(0) | game.move_mask(4), None).1, 2);
+    }
+
+    #[test]
+    fn move_ordering_keeps_solving_cheap() {
+        let mut game = BitBoard::new();
+        play(&mut game, "512134654565");
+        let ai = AIGame::with_seed(1);
+
+        assert_eq!(ai.solve(&mut game, &mut TranspositionTable::with_budget(16 << 20)), (5, -4));
+        //about 330k nodes with threat ordering, a worse order takes several times as many
+        assert!(ai.progress().nodes < 500_000, "{} nodes", ai.progress().nodes);
+    }
+
+    #[test]
+    fn table_bounds_cut_the_search() {
+        //lost for the player to move with a score of -4
+        let mut game = BitBoard::new();
+        play(&mut game, "512134654565");
+        let key = game.get_unique_key();
+        let ai = AIGame::with_seed(1);
+
+        //made up bounds, so an answer equal to them can only come from the table
+        let mut trans_table = TranspositionTable::with_budget(1 << 20);
+        trans_table.insert(key, Entry {value: 3, bound: Bound::Lower, best_move: None, depth: 64});
+        ai.progress.set(Progress::default());
+        assert_eq!(ai.negamax(&mut game, &mut trans_table, 1, 2, UNLIMITED_DEPTH), 3);
+        assert_eq!(ai.progress().nodes, 1);
+
+        let mut trans_table = TranspositionTable::with_budget(1 << 20);
+        trans_table.insert(key, Entry {value: -8, bound: Bound::Upper, best_move: None, depth: 64});
+        ai.progress.set(Progress::default());
+        assert_eq!(ai.negamax(&mut game, &mut trans_table, -6, -5, UNLIMITED_DEPTH), -8);
+        assert_eq!(ai.progress().nodes, 1);
+
+        //a bound on the wrong side of the window only narrows it, the search goes on to the true score
+        let mut trans_table = TranspositionTable::with_budget(1 << 20);
+        trans_table.insert(key, Entry {value: -8, bound: Bound::Lower, best_move: None, depth: 64});
+        ai.progress.set(Progress::default());
+        assert!(ai.negamax(&mut game, &mut trans_table, -6, -5, UNLIMITED_DEPTH) >= -5);
+        assert!(ai.progress().nodes > 1);
+    }
+
+    #[test]
+    fn table_best_move_is_searched_first() {
+        let mut game = BitBoard::new();
+        play(&mut game, "2636");
+        let ai = AIGame::with_seed(1);
+        let (moves, count) = ai.ordered_moves(&game, game.non_losing_moves(), None);
+        let last = moves[count - 1];
+        assert_eq!(count, 7);
+
+        //too shallow to stand in for the search, but its move still goes first
+        let mut trans_table = Recorder {table: TranspositionTable::with_budget(1 << 20), lookups: RefCell::new(Vec::new())};
+        trans_table.insert(game.get_unique_key(), Entry {value: 0, bound: Bound::Exact, best_move: Some(last), depth: 0});
+        ai.negamax(&mut game, &mut trans_table, 0, 1, 4);
+
+        game.play_move(last);
+        assert_eq!(trans_table.lookups.borrow()[1], game.get_unique_key());
+    }
+}
@@ -0,0 +1,263 @@
+use crate::scripts::ai::{AIGame, Outcome};
+use crate::scripts::bit_board::{BitBoard, GameState};
+use crate::scripts::evaluation::{ColumnResult, PositionEvaluation};
+use crate::scripts::trans_table::TranspositionTable;
+
+//how a move changed the game for the player who made it
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Judgement {
+    Best, //kept the best result the position offered
+    SlowerWin, //still wins, but lets the opponent hold out longer than the best move
+    Mistake, //gave up one step of the result: a win for a draw or a draw for a loss
+    Blunder, //turned a win into a loss
+    Unsolved, //the position could not be solved, so the move is not judged
+}
+
+impl Judgement {
+    pub fn is_flagged(&self) -> bool {
+        match self {
+            Judgement::SlowerWin | Judgement::Mistake | Judgement::Blunder => true,
+            Judgement::Best | Judgement::Unsolved => false,
+        }
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Judgement::Best => "best",
+            Judgement::SlowerWin => "wins more slowly",
+            Judgement::Mistake => "mistake",
+            Judgement::Blunder => "blunder",
+            Judgement::Unsolved => "not solved",
+        }
+    }
+}
+
+//one move of an analysed game, results are from the point of view of the player who made it
+#[derive(Copy, Clone, Debug)]
+pub struct MoveAnnotation {
+    pub ply: usize, //moves played before this one
+    pub col: usize,
+    pub red: bool, //made by red, the player moving first
+    pub judgement: Judgement,
+    pub before: Option<Outcome>, //result the position offered
+    pub after: Option<Outcome>, //result left after the move
+    pub best_col: Option<usize>, //a column keeping the result the position offered
+    pub best_score: Option<i64>, //exact scores, only near the end of the game where negamax is exact
+    pub score: Option<i64>,
+}
+
+impl MoveAnnotation {
+    //one line summary, columns counted from 1 like on the board
+    pub fn describe(&self) -> String {
+        self.describe_with("Red", "Yellow")
+    }
+
+    //describe with other names for the player moving first and the one moving second
+    pub fn describe_with(&self, first: &str, second: &str) -> String {
+        let player = if self.red {first} else {second};
+        let mut text = format!("{}. {} column {}: {}", self.ply + 1, player, self.col + 1, self.judgement.describe());
+
+        if let (Some(before), Some(after)) = (self.before, self.after) {
+            if before != after {
+                text.push_str(&format!(", {} became {}", outcome_name(before), outcome_name(after)));
+            }
+        }
+
+        if let (Judgement::SlowerWin, Some(best_score), Some(score)) = (self.judgement, self.best_score, self.score) {
+            text.push_str(&format!(", score {} where {} was possible", score, best_score));
+        }
+
+        if let (true, Some(best_col)) = (self.judgement.is_flagged(), self.best_col) {
+            text.push_str(&format!(" (column {} was best)", best_col + 1));
+        }
+
+        return text;
+    }
+}
+
+//replays a game, solves every position and judges the move played there, see PositionEvaluation.
+//the work is done one column at a time so the browser can stay responsive
+pub struct GameAnalysis {
+    evaluation: PositionEvaluation, //of the position before the move being analysed
+    moves: Vec<usize>,
+    annotations: Vec<MoveAnnotation>,
+}
+
+impl GameAnalysis {
+    pub fn new(width: usize, height: usize, moves: &[usize], ai: AIGame, table_bytes: usize) -> Result<Self, String> {
+        if !BitBoard::is_size_supported(width, height) || !TranspositionTable::fits_board(width, height) {
+            return Err(format!("Cannot analyse games on a {}x{} board", width, height));
+        }
+
+        let mut game = BitBoard::with_size(width, height);
+
+        for (ply, &col) in moves.iter().enumerate() {
+            if col >= width || !game.is_move_valid(col) {
+                return Err(format!("Illegal move {} at ply {}", col, ply + 1));
+            }
+
+            if game.play_turn(col)? != GameState::Default && ply + 1 < moves.len() {
+                return Err("Moves continue after the end of the game!".to_string());
+            }
+        }
+
+        Ok(Self {
+            evaluation: PositionEvaluation::new(&BitBoard::with_size(width, height), ai, table_bytes),
+            moves: moves.to_vec(),
+            annotations: Vec::new(),
+        })
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.annotations.len() == self.moves.len()
+    }
+
+    //moves analysed so far
+    pub fn completed(&self) -> usize {
+        self.annotations.len()
+    }
+
+    pub fn total(&self) -> usize {
+        self.moves.len()
+    }
+
+    pub fn annotations(&self) -> &[MoveAnnotation] {
+        &self.annotations
+    }
+
+    //moves that lost ground, see Judgement::is_flagged
+    pub fn flagged(&self) -> impl Iterator<Item = &MoveAnnotation> {
+        self.annotations.iter().filter(|annotation| annotation.judgement.is_flagged())
+    }
+
+    //looks at the next column of the current position, returns true once every move is annotated
+    pub fn step(&mut self) -> bool {
+        if self.is_done() {
+            return true;
+        }
+
+        //the played column can only be judged against all the others
+        if self.evaluation.step() {
+            self.annotate();
+        }
+
+        return self.is_done();
+    }
+
+    //judges the move played in the current position and moves on to the next one
+    fn annotate(&mut self) {
+        let ply = self.annotations.len();
+        let col = self.moves[ply];
+        let columns = self.evaluation.columns();
+        let played = columns[col];
+        //ties go to the played column, so a move that was as good as any is not flagged
+        let best_col = (0..columns.len())
+            .filter(|&i| columns[i].outcome().is_some())
+            .max_by_key(|&i| (columns[i].rank(), i == col));
+        let best = best_col.map(|i| columns[i]);
+        let known = best.and_then(|best| best.outcome()); //proven reachable, though an unknown column may do better
+        let complete = columns.iter().all(|result| !matches!(result, ColumnResult::Unknown));
+        let after = played.outcome();
+
+        let judgement = match (known, after) {
+            (Some(known), Some(after)) if played.rank().0 < best.unwrap().rank().0 => judge_outcomes(known, after),
+            (Some(known), Some(after)) if complete || after == Outcome::Win => {
+                match (best.and_then(|best| best.score()), played.score()) {
+                    (Some(best_score), Some(score)) if score < best_score => Judgement::SlowerWin,
+                    _ => judge_outcomes(known, after),
+                }
+            }
+            _ => Judgement::Unsolved,
+        };
+        let before = known.filter(|&known| judgement != Judgement::Unsolved || complete || known == Outcome::Win);
+
+        self.annotations.push(MoveAnnotation {
+            ply,
+            col,
+            red: self.evaluation.board().red_turn,
+            judgement,
+            before,
+            after,
+            best_col: best_col.filter(|_| before.is_some()),
+            best_score: best.and_then(|best| best.score()),
+            score: played.score(),
+        });
+
+        let mut board = self.evaluation.board().clone();
+        board.play_move(col);
+        self.evaluation.set_position(&board);
+    }
+}
+
+fn judge_outcomes(before: Outcome, after: Outcome) -> Judgement {
+    match (before, after) {
+        (Outcome::Win, Outcome::Loss) => Judgement::Blunder,
+        (Outcome::Win, Outcome::Draw) | (Outcome::Draw, Outcome::Loss) => Judgement::Mistake,
+        _ => Judgement::Best,
+    }
+}
+
+fn outcome_name(outcome: Outcome) -> &'static str {
+    match outcome {
+        Outcome::Win => "a win",
+        Outcome::Draw => "a draw",
+        Outcome::Loss => "a loss",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scripts::tablebase::{Tablebase};
+
+    //red lines up the bottom row while yellow stacks column 4 instead of blocking it
+    const GAME: [usize; 7] = [0, 4, 1, 4, 2, 4, 3];
+
+    fn analyse(width: usize, height: usize, moves: &[usize]) -> GameAnalysis {
+        let mut analysis = GameAnalysis::new(width, height, moves, AIGame::with_seed(1), 1 << 20).unwrap();
+
+        while !analysis.step() {}
+
+        return analysis;
+    }
+
+    #[test]
+    fn scores_match_the_tablebase() {
+        let tablebase = Tablebase::generate(5, 4);
+        let analysis = analyse(5, 4, &GAME);
+        let mut game = BitBoard::with_size(5, 4);
+
+        assert_eq!(analysis.completed(), GAME.len());
+
+        for annotation in analysis.annotations() {
+            let entry = tablebase.get(&game).unwrap();
+            assert_eq!(annotation.best_score, Some(entry.score(&game)), "ply {}", annotation.ply);
+            assert_eq!(annotation.score, tablebase.move_score(&mut game, annotation.col), "ply {}", annotation.ply);
+            assert_eq!(annotation.before, Some(entry.outcome), "ply {}", annotation.ply);
+            game.play_move(annotation.col);
+        }
+    }
+
+    #[test]
+    fn flags_the_moves_that_changed_the_result() {
+        let analysis = analyse(5, 4, &GAME);
+        let flagged: Vec<usize> = analysis.flagged().map(|annotation| annotation.ply).collect();
+        assert_eq!(flagged, vec![0, 1, 5]);
+
+        let missed_block = analysis.annotations()[5];
+        assert_eq!((missed_block.judgement, missed_block.after, missed_block.best_col), (Judgement::Mistake, Some(Outcome::Loss), Some(3)));
+        assert_eq!(missed_block.describe_with("Ann", "Bob"), "6. Bob column 5: mistake, a draw became a loss (column 4 was best)");
+        assert_eq!(analysis.annotations()[6].judgement, Judgement::Best);
+    }
+
+    #[test]
+    fn rejects_games_that_cannot_be_analysed() {
+        let new = |width, height, moves: &[usize]| GameAnalysis::new(width, height, moves, AIGame::with_seed(1), 1 << 20).is_ok();
+
+        assert!(new(5, 4, &GAME[..3]));
+        assert!(!new(5, 4, &[0, 0, 0, 0, 0]));
+        assert!(!new(5, 4, &[5]));
+        assert!(!new(5, 4, &[0, 4, 1, 4, 2, 4, 3, 3]));
+        assert!(!new(8, 6, &[]));
+    }
+}
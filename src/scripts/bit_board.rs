@@ -11,46 +11,89 @@ pub enum GameState {
 
 use GameState::{Win, Loss, Tie, Default};
 
+#[derive(Clone)]
 pub struct BitBoard {
     pub player_mask: u64, //first 49 bits used to store current player position
     pub total_mask: u64, //first 49 bits used to store all played coins
-    pub complete_board: u64,
     pub bottom_row: u64,
     num_moves: usize, //total number of moves played in the current game
     pub red_turn: bool, //used to signify whose turn it is
     pub state: GameState, //current game state
+    width: usize, //number of columns
+    height: usize, //number of rows, each column also keeps one spare bit above its top cell
 }
 
 //Implementation of board
 impl BitBoard {
     pub fn new() -> Self {
+        Self::with_size(WIDTH, HEIGHT)
+    }
+
+    //board of any size accepted by is_size_supported
+    pub fn with_size(width: usize, height: usize) -> Self {
+        assert!(Self::is_size_supported(width, height), "Board too large for a 64 bit mask!");
         let mut temp:u64 = 1;
 
-        for col in 1..WIDTH {
-            temp |= 1 << (HEIGHT + 1) * col
+        for col in 1..width {
+            temp |= 1 << ((height + 1) * col)
         }
 
         Self {
             player_mask: 0, 
             total_mask: 0,
-            complete_board: (temp << HEIGHT) - 1,
             bottom_row: temp,
             num_moves: 0,
             red_turn: true,
             state: Default,
+            width,
+            height,
         }
     }
 
+    //the columns (plus their spare bit) have to fit in 64 bits,
+    //and so does the longest shift used to find alignments, three diagonal steps of height + 2
+    pub fn is_size_supported(width: usize, height: usize) -> bool {
+        return width >= 1 && height >= 1 && (height + 1) * width <= 64 && 3 * (height + 2) < 64;
+    }
+
+    //rebuilds the position behind a get_unique_key value, the game is assumed to be running
+    pub fn from_key(width: usize, height: usize, key: u64) -> Self {
+        let mut board = Self::with_size(width, height);
+        let col_bits: u64 = (1 << (height + 1)) - 1;
+
+        for col in 0..width {
+            //a column holding n coins has a key block between 2^n - 1 and 2^(n+1) - 2
+            let block = (key >> ((height + 1) * col)) & col_bits;
+            let coins = 63 - (block + 1).leading_zeros() as usize;
+            board.total_mask |= ((1 << coins) - 1) << ((height + 1) * col);
+            board.num_moves += coins;
+        }
+
+        let current = key - board.total_mask;
+        board.red_turn = board.num_moves.is_multiple_of(2);
+        board.player_mask = if board.red_turn {current} else {current ^ board.total_mask};
+
+        return board;
+    }
+
+    pub fn width(&self) -> usize {
+        return self.width;
+    }
+
+    pub fn height(&self) -> usize {
+        return self.height;
+    }
+
     pub fn top_col_mask(&self, col: usize) -> u64 {
-        return (1 << (HEIGHT - 1)) << ((HEIGHT + 1) * col);
+        return (1 << (self.height - 1)) << ((self.height + 1) * col);
     }
 
     pub fn bottom_col_mask(&self, col: usize) -> u64 {
-        return 1 << ((HEIGHT + 1) * col);
+        return 1 << ((self.height + 1) * col);
     }
 
     pub fn full_col_mask(&self, col: usize) -> u64 {
-        return ((1 << HEIGHT) - 1) << ((HEIGHT + 1) * col);
+        return ((1 << self.height) - 1) << ((self.height + 1) * col);
     }
 
     pub fn is_move_valid(&self, col: usize) -> bool {
@@ -67,6 +110,25 @@ impl BitBoard {
         return current + self.total_mask;
     }
 
+    pub fn get_mirrored_key(&self) -> u64 {
+        let key = self.get_unique_key();
+        let col_bits: u64 = (1 << (self.height + 1)) - 1;
+        let mut mirrored: u64 = 0;
+
+        //each column takes height + 1 bits of the key, so swap the blocks left to right
+        for col in 0..self.width {
+            let block = (key >> ((self.height + 1) * col)) & col_bits;
+            mirrored |= block << ((self.height + 1) * (self.width - 1 - col));
+        }
+
+        return mirrored;
+    }
+
+    //same key for a position and its mirror image, used to share results between the two
+    pub fn get_canonical_key(&self) -> u64 {
+        return self.get_unique_key().min(self.get_mirrored_key());
+    }
+
     pub fn undo_move(&mut self, col: usize) -> Result<GameState, String> {
         if self.total_mask & self.bottom_col_mask(col) == 0 {
             return Err("Column in empty!".to_string())
@@ -115,20 +177,90 @@ impl BitBoard {
     }
 
     pub fn is_draw(&self) -> bool {
-        return self.num_moves >= WIDTH*HEIGHT - 1;
+        return self.num_moves >= self.width*self.height - 1;
     }
 
     pub fn get_num_moves(&self) -> usize {
         return self.num_moves;
     }
 
-    pub fn is_winning_move(&mut self, col: usize) -> bool {
-        self.play_move(col);
-        let position:u64 = if self.red_turn {self.total_mask ^ self.player_mask}  else {self.player_mask};
-        self.undo_move(col);
+    //stones of the player whose turn it is
+    pub fn current_position(&self) -> u64 {
+        return if self.red_turn {self.player_mask} else {self.player_mask ^ self.total_mask};
+    }
+
+    //stones of the player who moved last
+    pub fn opponent_position(&self) -> u64 {
+        return self.current_position() ^ self.total_mask;
+    }
+
+    //cell a coin dropped in col would land on
+    pub fn move_mask(&self, col: usize) -> u64 {
+        return (self.total_mask + self.bottom_col_mask(col)) & self.full_col_mask(col);
+    }
+
+    pub fn is_winning_move(&self, col: usize) -> bool {
+        return self.is_alignment(self.current_position() | self.move_mask(col));
+    }
+
+    //true if the opponent would win by dropping a coin in col
+    pub fn is_opponent_winning_move(&self, col: usize) -> bool {
+        return self.is_alignment(self.opponent_position() | self.move_mask(col));
+    }
+
+    //every playable or future cell, without the spare bit on top of each column
+    pub fn board_mask(&self) -> u64 {
+        return self.bottom_row * ((1 << self.height) - 1);
+    }
+
+    //empty cells that would complete an alignment of four for the stones in position
+    pub fn winning_cells(&self, position: u64) -> u64 {
+        let h = self.height;
+        //vertical direction
+        let mut r:u64 = (position << 1) & (position << 2) & (position << 3);
+
+        //horizontal and both diagonals, looking for three stones around the cell in either order
+        for shift in [h + 1, h, h + 2] {
+            let p:u64 = (position << shift) & (position << (2 * shift));
+            r |= p & (position << (3 * shift));
+            r |= p & (position >> shift);
+            let p:u64 = (position >> shift) & (position >> (2 * shift));
+            r |= p & (position << shift);
+            r |= p & (position >> (3 * shift));
+        }
+
+        return r & (self.board_mask() ^ self.total_mask);
+    }
+
+    //cells the player to move can play without letting the opponent win on the next move:
+    //an opponent threat has to be blocked, and no coin may go right below an opponent winning cell
+    pub fn non_losing_moves(&self) -> u64 {
+        let mut possible = self.get_height_mask() & self.board_mask();
+        let opponent_wins = self.winning_cells(self.opponent_position());
+        let forced = possible & opponent_wins;
+
+        if forced != 0 {
+            if forced & (forced - 1) != 0 {
+                //two threats at once cannot both be blocked
+                return 0;
+            }
+            possible = forced;
+        }
+
+        return possible & !(opponent_wins >> 1);
+    }
+
+    //number of winning cells the player to move would own after playing col, used to order moves
+    pub fn move_score(&self, col: usize) -> u32 {
+        let position = self.current_position() | self.move_mask(col);
+        return (self.winning_cells(position) & !self.move_mask(col)).count_ones();
+    }
+
+    pub fn is_alignment(&self, position: u64) -> bool {
+        let height = self.height;
         //horizontal direction
-        let n:u64 = position & (position >> (HEIGHT + 1));
-        if n & (n >> 2 * (HEIGHT + 1)) > 0 {
+        let n:u64 = position & (position >> (height + 1));
+        if n & (n >> (2 * (height + 1))) > 0 {
             return true;
         }
         //vertical direction
@@ -137,17 +269,89 @@ impl BitBoard {
             return true;
         }
         //diagonal = (/) direction
-        let n:u64 = position & (position >> HEIGHT);
-        if n & (n >> 2 * HEIGHT) > 0 {
+        let n:u64 = position & (position >> height);
+        if n & (n >> (2 * height)) > 0 {
             return true;
         }
         
         //diagonal = (\) direction
-        let n:u64 = position & (position >> (HEIGHT + 2));
-        if n & (n >> 2 * (HEIGHT + 2)) > 0 {
+        let n:u64 = position & (position >> (height + 2));
+        if n & (n >> (2 * (height + 2))) > 0 {
             return true;
         }
         
         return false;
     }
 } 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_must_fit_the_masks() {
+        assert!(BitBoard::is_size_supported(7, 6));
+        assert!(BitBoard::is_size_supported(7, 8));
+        assert!(BitBoard::is_size_supported(3, 19));
+
+        //columns overflow 64 bits
+        assert!(!BitBoard::is_size_supported(8, 8));
+        //a diagonal alignment shift overflows
+        assert!(!BitBoard::is_size_supported(1, 63));
+        assert!(!BitBoard::is_size_supported(2, 31));
+        assert!(!BitBoard::is_size_supported(0, 6));
+    }
+
+    #[test]
+    fn tallest_boards_find_alignments() {
+        let mut game = BitBoard::with_size(3, 19);
+
+        for _ in 0..3 {
+            game.play_move(0);
+            game.play_move(1);
+        }
+
+        assert!(game.is_winning_move(0));
+        assert!(!game.is_winning_move(2));
+    }
+
+    #[test]
+    fn non_losing_moves_block_threats() {
+        let mut game = BitBoard::new();
+
+        for col in [0, 0, 1, 1, 2, 2] {
+            game.play_move(col);
+        }
+
+        game.play_move(6);
+        //the only move that does not lose blocks the bottom row
+        assert_eq!(game.non_losing_moves(), game.move_mask(3));
+
+        let mut game = BitBoard::new();
+
+        for col in [1, 1, 2, 2, 3, 3, 6] {
+            game.play_move(col);
+        }
+
+        //an open three on the bottom row threatens both ends at once
+        assert_eq!(game.non_losing_moves(), 0);
+    }
+
+    #[test]
+    fn move_score_counts_new_threats() {
+        let mut game = BitBoard::new();
+
+        //red on columns 2 and 3 of the bottom row, yellow stacked on column 6
+        for col in [2, 6, 3, 6] {
+            game.play_move(col);
+        }
+
+        //an open three threatens both ends, a broken three only its gap
+        assert_eq!(game.move_score(4), 2);
+        assert_eq!(game.move_score(1), 2);
+        assert_eq!(game.move_score(5), 1);
+        assert_eq!(game.move_score(0), 1);
+        assert_eq!(game.move_score(3), 0);
+        assert_eq!(game.move_score(6), 0);
+    }
+}
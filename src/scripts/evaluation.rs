@@ -0,0 +1,264 @@
+use crate::scripts::ai::{AIGame, Outcome};
+use crate::scripts::bit_board::{BitBoard};
+use crate::scripts::trans_table::{TranspositionTable};
+
+//proof tree size for positions beyond the reach of exact negamax, small so each column takes well under a second
+const PN_NODES: usize = 1 << 20;
+
+//what is known about playing one column, for the player to move
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ColumnResult {
+    Full,
+    Score(i64), //exact negamax score
+    Solved(Outcome), //result proven by proof-number search
+    Unknown, //proof-number search ran out of nodes
+}
+
+impl ColumnResult {
+    pub fn outcome(&self) -> Option<Outcome> {
+        match *self {
+            ColumnResult::Score(score) => Some(Outcome::from_score(score)),
+            ColumnResult::Solved(outcome) => Some(outcome),
+            ColumnResult::Full | ColumnResult::Unknown => None,
+        }
+    }
+
+    pub fn score(&self) -> Option<i64> {
+        match *self {
+            ColumnResult::Score(score) => Some(score),
+            _ => None,
+        }
+    }
+
+    //orders results from worst to best, unknown and full columns below all
+    pub fn rank(&self) -> (i32, i64) {
+        let outcome = match self.outcome() {
+            Some(Outcome::Win) => 3,
+            Some(Outcome::Draw) => 2,
+            Some(Outcome::Loss) => 1,
+            None => 0,
+        };
+
+        (outcome, self.score().unwrap_or(0))
+    }
+}
+
+//solves every column of a position one at a time, so the browser can stay responsive. positions negamax
+//solves exactly get scored, earlier ones only get their result from proof-number search.
+//the tables are kept when moving on to another position
+pub struct PositionEvaluation {
+    ai: AIGame,
+    trans_table: TranspositionTable, //for negamax
+    pn_table: TranspositionTable, //for proof-number search, which stores different values
+    board: BitBoard,
+    columns: Vec<ColumnResult>, //columns looked at so far
+}
+
+impl PositionEvaluation {
+    pub fn new(game: &BitBoard, ai: AIGame, table_bytes: usize) -> Self {
+        Self {
+            ai,
+            trans_table: TranspositionTable::with_budget(table_bytes),
+            pn_table: TranspositionTable::with_budget(table_bytes),
+            board: game.clone(),
+            columns: Vec::new(),
+        }
+    }
+
+    //starts over on another position
+    pub fn set_position(&mut self, game: &BitBoard) {
+        self.board = game.clone();
+        self.columns.clear();
+        self.trans_table.new_search();
+        self.pn_table.new_search();
+    }
+
+    pub fn board(&self) -> &BitBoard {
+        &self.board
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.columns.len() == self.board.width()
+    }
+
+    //results of the columns looked at so far, from the left
+    pub fn columns(&self) -> &[ColumnResult] {
+        &self.columns
+    }
+
+    //best column and its result once every column is looked at. a proven result is reachable,
+    //though an unknown column may do better
+    pub fn best(&self) -> Option<(usize, ColumnResult)> {
+        if !self.is_done() {
+            return None;
+        }
+
+        (0..self.columns.len())
+            .filter(|&col| self.columns[col].outcome().is_some())
+            .max_by_key(|&col| self.columns[col].rank())
+            .map(|col| (col, self.columns[col]))
+    }
+
+    //moves the winning side still makes, its winning one included, for an exact score of a column. None for a draw
+    pub fn moves_to_win(&self, score: i64) -> Option<usize> {
+        if score == 0 {
+            return None;
+        }
+
+        //a score of s means the winner's last coin goes in at ply cells + 2 - 2s at the latest, which has to be
+        //one of the winner's plies: odd counted from here for the player to move, even for the opponent
+        let n = self.board.get_num_moves();
+        let mut ply = self.board.width() * self.board.height() + 2 - 2 * score.unsigned_abs() as usize;
+
+        if (ply - n) % 2 != (score > 0) as usize {
+            ply -= 1;
+        }
+
+        Some((ply - n).div_ceil(2))
+    }
+
+    //short label for a column: win or loss in so many moves when the score is exact, otherwise only the result
+    pub fn describe_column(&self, col: usize) -> String {
+        match self.columns.get(col) {
+            None => "...".to_string(),
+            Some(ColumnResult::Full) => String::new(),
+            Some(&ColumnResult::Score(score)) => match self.moves_to_win(score) {
+                Some(moves) if score > 0 => format!("Win in {}", moves),
+                Some(moves) => format!("Loss in {}", moves),
+                None => "Draw".to_string(),
+            },
+            Some(ColumnResult::Solved(Outcome::Win)) => "Win".to_string(),
+            Some(ColumnResult::Solved(Outcome::Draw)) => "Draw".to_string(),
+            Some(ColumnResult::Solved(Outcome::Loss)) => "Loss".to_string(),
+            Some(ColumnResult::Unknown) => "?".to_string(),
+        }
+    }
+
+    //how good the position is for the player to move, from -1 for losing at once to 1 for winning at once.
+    //results proven without a score count as the slowest win or loss. None until every column is looked at,
+    //or when it is not known whether the player to move loses
+    pub fn advantage(&self) -> Option<f64> {
+        let (_, best) = self.best()?;
+        let complete = self.columns.iter().all(|result| *result != ColumnResult::Unknown);
+        let longest = (self.board.width() * self.board.height() - self.board.get_num_moves()).div_ceil(2);
+        //sooner wins and sooner losses score further from zero, up to half of the range
+        let speed = match best.score().and_then(|score| self.moves_to_win(score)) {
+            Some(moves) => 1.0 - (moves - 1) as f64 / longest.max(1) as f64,
+            None => 0.0,
+        };
+
+        match best.outcome()? {
+            Outcome::Win => Some(0.5 + 0.5 * speed),
+            Outcome::Draw => Some(0.0),
+            Outcome::Loss if complete => Some(-0.5 - 0.5 * speed),
+            Outcome::Loss => None,
+        }
+    }
+
+    //looks at the next column, returns true once every column is done
+    pub fn step(&mut self) -> bool {
+        if !self.is_done() {
+            let result = self.column_result(self.columns.len());
+            self.columns.push(result);
+        }
+
+        return self.is_done();
+    }
+
+    fn column_result(&mut self, col: usize) -> ColumnResult {
+        if !self.board.is_move_valid(col) {
+            return ColumnResult::Full;
+        }
+
+        if self.ai.is_exact(&self.board) {
+            return ColumnResult::Score(self.ai.score_move(&mut self.board, &mut self.trans_table, col));
+        }
+
+        if self.board.is_winning_move(col) {
+            return ColumnResult::Solved(Outcome::Win);
+        }
+
+        self.board.play_move(col);
+        let outcome = self.ai.pn_solve_within(&self.board, &mut self.pn_table, PN_NODES);
+        let _ = self.board.undo_move(col);
+
+        //the opponent's result, seen from the other side
+        match outcome {
+            Some(Outcome::Win) => ColumnResult::Solved(Outcome::Loss),
+            Some(Outcome::Draw) => ColumnResult::Solved(Outcome::Draw),
+            Some(Outcome::Loss) => ColumnResult::Solved(Outcome::Win),
+            None => ColumnResult::Unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scripts::tablebase::{Tablebase};
+
+    fn evaluate(game: &BitBoard) -> PositionEvaluation {
+        let mut evaluation = PositionEvaluation::new(game, AIGame::with_seed(1), 1 << 20);
+
+        while !evaluation.step() {}
+
+        return evaluation;
+    }
+
+    #[test]
+    fn columns_match_the_tablebase() {
+        let tablebase = Tablebase::generate(5, 4);
+
+        for (mut game, _) in tablebase.positions().step_by(1009) {
+            let evaluation = evaluate(&game);
+
+            for col in 0..game.width() {
+                let result = evaluation.columns()[col];
+
+                if !game.is_move_valid(col) {
+                    assert_eq!(result, ColumnResult::Full);
+                    assert_eq!(evaluation.describe_column(col), "");
+                    continue;
+                }
+
+                let score = tablebase.move_score(&mut game, col).unwrap();
+                assert_eq!(result, ColumnResult::Score(score), "key {} column {}", game.get_unique_key(), col);
+
+                //plies until the game ends, the winner makes every other one of them ending with the last
+                let distance = if game.is_winning_move(col) {
+                    Some(1)
+                } else {
+                    game.play_move(col);
+                    let distance = tablebase.get(&game).map(|entry| entry.distance + 1);
+                    let _ = game.undo_move(col);
+                    distance
+                };
+                let moves = distance.filter(|_| score != 0).map(|distance| distance.div_ceil(2));
+                assert_eq!(evaluation.moves_to_win(score), moves, "key {} column {}", game.get_unique_key(), col);
+
+                let label = match moves {
+                    Some(moves) if score > 0 => format!("Win in {}", moves),
+                    Some(moves) => format!("Loss in {}", moves),
+                    None => "Draw".to_string(),
+                };
+                assert_eq!(evaluation.describe_column(col), label);
+            }
+        }
+    }
+
+    #[test]
+    fn advantage_favours_the_winning_side() {
+        let tablebase = Tablebase::generate(5, 4);
+
+        for outcome in [Outcome::Win, Outcome::Draw, Outcome::Loss] {
+            let (game, _) = tablebase.positions().find(|(_, entry)| entry.outcome == outcome).unwrap();
+            let advantage = evaluate(&game).advantage().unwrap();
+
+            match outcome {
+                Outcome::Win => assert!(advantage > 0.5 && advantage <= 1.0, "{}", advantage),
+                Outcome::Draw => assert_eq!(advantage, 0.0),
+                Outcome::Loss => assert!((-1.0..-0.5).contains(&advantage), "{}", advantage),
+            }
+        }
+    }
+}
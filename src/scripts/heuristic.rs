@@ -0,0 +1,89 @@
+use crate::scripts::bit_board::{BitBoard};
+
+pub const WIN_SCORE: i64 = 1_000_000; //above any evaluation, wins found sooner score higher
+const THREAT_WEIGHT: i64 = 4;
+
+//estimate of a running game for the player to move, kept well below WIN_SCORE
+pub trait Evaluator {
+    fn evaluate(&self, game: &BitBoard) -> i64;
+}
+
+//open winning cells of each side, then coins in the centre column
+pub struct Handcrafted;
+
+impl Evaluator for Handcrafted {
+    fn evaluate(&self, game: &BitBoard) -> i64 {
+        let current = game.current_position();
+        let opponent = game.opponent_position();
+        let threats = game.winning_cells(current).count_ones() as i64 - game.winning_cells(opponent).count_ones() as i64;
+        let centre = game.full_col_mask(game.width() / 2);
+        let centre_coins = (current & centre).count_ones() as i64 - (opponent & centre).count_ones() as i64;
+
+        return THREAT_WEIGHT * threats + centre_coins;
+    }
+}
+
+//negamax looking depth moves ahead and evaluating the positions it stops at
+pub fn negamax(game: &mut BitBoard, evaluator: &dyn Evaluator, depth: usize, mut alpha: i64, beta: i64) -> i64 {
+    let cells = game.width() * game.height();
+
+    for col in 0..game.width() {
+        if game.is_move_valid(col) && game.is_winning_move(col) {
+            return WIN_SCORE + (cells - game.get_num_moves()) as i64;
+        }
+    }
+
+    //the last coin cannot win once the move before it did not
+    if game.get_num_moves() >= cells - 1 {
+        return 0;
+    }
+
+    if depth == 0 {
+        return evaluator.evaluate(game);
+    }
+
+    for i in 0..game.width() {
+        let col = centre_first(game.width(), i);
+
+        if game.is_move_valid(col) {
+            game.play_move(col);
+            let score = -negamax(game, evaluator, depth - 1, -beta, -alpha);
+            let _ = game.undo_move(col);
+
+            if score >= beta {
+                return score;
+            }
+
+            if score > alpha {
+                alpha = score;
+            }
+        }
+    }
+
+    return alpha;
+}
+
+//score of every valid column for the player to move, None for full columns
+pub fn move_scores(game: &BitBoard, evaluator: &dyn Evaluator, depth: usize) -> Vec<Option<i64>> {
+    let mut game = game.clone();
+    let cells = game.width() * game.height();
+
+    (0..game.width()).map(|col| {
+        if !game.is_move_valid(col) {
+            return None;
+        }
+
+        if game.is_winning_move(col) {
+            return Some(WIN_SCORE + (cells - game.get_num_moves()) as i64);
+        }
+
+        game.play_move(col);
+        let score = -negamax(&mut game, evaluator, depth, -WIN_SCORE * 2, WIN_SCORE * 2);
+        let _ = game.undo_move(col);
+        Some(score)
+    }).collect()
+}
+
+fn centre_first(width: usize, i: usize) -> usize {
+    return (width as i64 / 2 + (1 - 2 * (i as i64 % 2)) * (i as i64 + 1) / 2) as usize;
+}
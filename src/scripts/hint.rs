@@ -0,0 +1,214 @@
+use crate::scripts::ai::{AIGame, RootSearch};
+use crate::scripts::bit_board::{BitBoard};
+use crate::scripts::trans_table::{TranspositionTable};
+
+//why a column is worth playing, worked out from the board after the search picked it
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Reason {
+    WinsNow,
+    BlocksThreat, //the opponent would win in this column on their next move
+    DoubleThreat, //leaves two winning cells the opponent cannot both cover
+    AvoidsCellAbove, //other columns would let the opponent win on top of the coin
+    ClaimsOddEvenThreat, //new winning cell on a row that suits the player, odd rows for red and even rows for yellow
+    OnlyNonLosingMove, //every other column loses
+    Strongest, //none of the above, the search simply rates it highest
+}
+
+impl Reason {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Reason::WinsNow => "wins right away",
+            Reason::BlocksThreat => "blocks a threat the opponent would win with next move",
+            Reason::DoubleThreat => "creates two threats, the opponent can only stop one",
+            Reason::AvoidsCellAbove => "does not give the opponent a winning cell on top, unlike other columns",
+            Reason::ClaimsOddEvenThreat => "claims a threat on a row that suits you for the endgame",
+            Reason::OnlyNonLosingMove => "is the only move that does not lose",
+            Reason::Strongest => "is rated best by the search",
+        }
+    }
+}
+
+//suggested column for the player to move and why
+#[derive(Clone, Debug)]
+pub struct Hint {
+    pub col: usize,
+    pub score: Option<i64>, //search score of the column, None if it came from the book
+    pub reasons: Vec<Reason>, //most important first, never empty
+}
+
+impl Hint {
+    //one line summary, columns counted from 1 like on the board
+    pub fn describe(&self) -> String {
+        let reasons: Vec<&str> = self.reasons.iter().map(|reason| reason.describe()).collect();
+        format!("Column {}: {}", self.col + 1, reasons.join(", "))
+    }
+}
+
+//finds a hint one root column at a time like a regular move search, so the browser can stay responsive
+pub struct HintSearch {
+    ai: AIGame,
+    trans_table: TranspositionTable,
+    board: BitBoard,
+    root: RootSearch,
+}
+
+impl HintSearch {
+    pub fn new(game: &BitBoard, mut ai: AIGame, table_bytes: usize) -> Self {
+        let root = ai.start_search(game);
+
+        Self {
+            ai,
+            trans_table: TranspositionTable::with_budget(table_bytes),
+            board: game.clone(),
+            root,
+        }
+    }
+
+    //scores the next root column, returns true once the hint is ready
+    pub fn step(&mut self) -> bool {
+        self.ai.search_step(&mut self.root, &mut self.board, &mut self.trans_table)
+    }
+
+    pub fn completed(&self) -> usize {
+        self.root.completed()
+    }
+
+    pub fn total(&self) -> usize {
+        self.root.total()
+    }
+
+    //best column found so far with its explanation
+    pub fn hint(&mut self) -> Hint {
+        let col = self.ai.chosen_move(&self.root);
+        explain(&self.board, col, self.root.scores())
+    }
+}
+
+//reasons for playing col, scores are the search scores of every column where known
+pub fn explain(game: &BitBoard, col: usize, scores: &[Option<i64>]) -> Hint {
+    let mut reasons = Vec::new();
+    let current = game.current_position();
+    let opponent = game.opponent_position();
+    let opponent_wins = game.winning_cells(opponent);
+    let valid: Vec<usize> = (0..game.width()).filter(|&c| game.is_move_valid(c)).collect();
+
+    if game.is_winning_move(col) {
+        reasons.push(Reason::WinsNow);
+    } else {
+        if game.is_opponent_winning_move(col) {
+            reasons.push(Reason::BlocksThreat);
+        }
+
+        //the board after the move
+        let cell = game.move_mask(col);
+        let position = current | cell;
+        let total = game.total_mask | cell;
+        let playable = (total + game.bottom_row) & game.board_mask();
+        let wins = game.winning_cells(position) & !cell;
+        let threats = wins & playable;
+
+        //two cells to win on next move, or one with another right above it
+        let double = threats.count_ones() >= 2 || threats & (wins >> 1) != 0;
+
+        if double && opponent_wins & playable & !cell == 0 {
+            reasons.push(Reason::DoubleThreat);
+        }
+
+        let cell_above = |c: usize| (game.move_mask(c) << 1) & game.full_col_mask(c);
+        let gives_cell_above = |c: usize| opponent_wins & cell_above(c) != 0;
+
+        if !gives_cell_above(col) && valid.iter().any(|&c| c != col && gives_cell_above(c)) {
+            reasons.push(Reason::AvoidsCellAbove);
+        }
+
+        //red moves first and profits from threats on odd rows counted from the bottom, yellow from even ones
+        let odd_rows = game.bottom_row * (0x5555_5555_5555_5555 & ((1 << game.height()) - 1));
+        let rows = if game.red_turn {odd_rows} else {(odd_rows << 1) & game.board_mask()};
+        let new_threats = wins & !game.winning_cells(current) & !playable;
+
+        if new_threats & rows != 0 {
+            reasons.push(Reason::ClaimsOddEvenThreat);
+        }
+    }
+
+    //a move winning at once is played without being searched, its score follows from the moves left
+    let score = if game.is_winning_move(col) {
+        Some(((game.width() * game.height() + 1 - game.get_num_moves()) / 2) as i64)
+    } else {
+        scores.get(col).copied().flatten()
+    };
+    let others: Vec<Option<i64>> = valid.iter().filter(|&&c| c != col).map(|&c| scores.get(c).copied().flatten()).collect();
+
+    if score.is_some_and(|score| score >= 0) && !others.is_empty() && others.iter().all(|other| other.is_some_and(|other| other < 0)) {
+        reasons.push(Reason::OnlyNonLosingMove);
+    }
+
+    if reasons.is_empty() {
+        reasons.push(Reason::Strongest);
+    }
+
+    return Hint {
+        col,
+        score,
+        reasons,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(line: &str) -> BitBoard {
+        let mut game = BitBoard::new();
+
+        for c in line.chars() {
+            game.play_move(c.to_digit(10).unwrap() as usize);
+        }
+
+        return game;
+    }
+
+    #[test]
+    fn a_winning_move_wins_now() {
+        let hint = explain(&play("010101"), 0, &[]);
+        assert_eq!(hint.reasons, vec![Reason::WinsNow]);
+        assert_eq!(hint.score, Some(18));
+        assert_eq!(hint.describe(), "Column 1: wins right away");
+    }
+
+    #[test]
+    fn blocking_the_only_threat_is_the_only_non_losing_move() {
+        let scores = [Some(0), Some(-5), Some(-5), Some(-5), Some(-5), Some(-5), Some(-5)];
+        let hint = explain(&play("01010"), 0, &scores);
+
+        assert_eq!(hint.score, Some(0));
+        assert!(hint.reasons.contains(&Reason::BlocksThreat));
+        assert!(hint.reasons.contains(&Reason::OnlyNonLosingMove));
+    }
+
+    #[test]
+    fn three_in_an_open_row_is_a_double_threat() {
+        let hint = explain(&play("1122"), 3, &[]);
+        assert!(hint.reasons.contains(&Reason::DoubleThreat));
+        assert!(!explain(&play("1122"), 6, &[]).reasons.contains(&Reason::DoubleThreat));
+    }
+
+    #[test]
+    fn without_a_reason_the_search_decides() {
+        let hint = explain(&BitBoard::new(), 3, &[]);
+        assert_eq!((hint.score, hint.reasons), (None, vec![Reason::Strongest]));
+    }
+
+    #[test]
+    fn search_finds_the_winning_column() {
+        let game = play("010101");
+        let mut search = HintSearch::new(&game, AIGame::with_seed(1), 1 << 20);
+
+        while !search.step() {}
+
+        let hint = search.hint();
+        assert_eq!(hint.col, 0);
+        assert_eq!(hint.reasons[0], Reason::WinsNow);
+        assert_eq!(hint.score, Some(18));
+    }
+}
@@ -0,0 +1,119 @@
+use crate::scripts::ai::{AIGame};
+use crate::scripts::bit_board::{BitBoard};
+use crate::scripts::search_control::{CancelToken, ProgressCallback};
+use crate::scripts::trans_table::{SharedTable};
+use std::thread;
+
+//number of search threads to use when the caller has no preference
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+//Lazy SMP: every thread searches the same root with its own move order and a shared table,
+//so results found by the helpers cut the work of the main thread, whose answer is returned.
+//Cancelling the token stops the main thread, which then stops the helpers.
+//Progress is reported by the main thread, so its node count leaves out the helpers.
+//Fails if the main thread panicked, a helper panicking only costs its share of the table
+pub fn search(game: &BitBoard, trans_table: &SharedTable, threads: usize, cancel: &CancelToken, on_progress: Option<ProgressCallback>) -> Result<(usize, i64), String> {
+    run(game, trans_table, threads, cancel, on_progress, |ai, game, trans_table| ai.search(game, trans_table))
+}
+
+//AIGame::solve the same way: every thread narrows the score down with null window searches,
+//helpers settle subtrees in their own order so the main thread finds them in the table
+pub fn solve(game: &BitBoard, trans_table: &SharedTable, threads: usize, cancel: &CancelToken, on_progress: Option<ProgressCallback>) -> Result<(usize, i64), String> {
+    run(game, trans_table, threads, cancel, on_progress, |ai, game, trans_table| ai.solve(game, trans_table))
+}
+
+//runs work on every thread, see search
+fn run<F>(game: &BitBoard, trans_table: &SharedTable, threads: usize, cancel: &CancelToken, on_progress: Option<ProgressCallback>, work: F) -> Result<(usize, i64), String>
+where
+    F: Fn(&AIGame, &mut BitBoard, &mut SharedTable) -> (usize, i64) + Sync,
+{
+    let helpers_done = CancelToken::new();
+    let mut on_progress = on_progress;
+    trans_table.new_search();
+
+    thread::scope(|scope| {
+        let mut workers = Vec::new();
+
+        for offset in 0..threads.max(1) {
+            let mut game = game.clone();
+            let mut trans_table = trans_table.clone();
+            let cancel = if offset == 0 {cancel.clone()} else {helpers_done.clone()};
+            let on_progress = if offset == 0 {on_progress.take()} else {None};
+            let work = &work;
+
+            workers.push(scope.spawn(move || {
+                let mut ai = AIGame::staggered(offset, cancel);
+
+                if let Some(callback) = on_progress {
+                    ai.set_progress_callback(callback);
+                }
+
+                work(&ai, &mut game, &mut trans_table)
+            }));
+        }
+
+        //helpers only exist to fill the table, their move orders can settle ties differently
+        let mut workers = workers.into_iter();
+        let result = workers.next().unwrap().join();
+        helpers_done.cancel();
+
+        //joined here so a panicking helper is not rethrown when the scope ends
+        for helper in workers {
+            let _ = helper.join();
+        }
+
+        result.map_err(|_| "The search thread panicked!".to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scripts::tablebase::{Tablebase};
+    use crate::scripts::trans_table::{ReplacementPolicy};
+
+    fn late_position() -> BitBoard {
+        let mut game = BitBoard::new();
+
+        for c in "3332224441115566".chars() {
+            game.play_move(c.to_digit(10).unwrap() as usize);
+        }
+
+        return game;
+    }
+
+    #[test]
+    fn agrees_with_a_single_thread() {
+        let game = late_position();
+        let (_, expected) = AIGame::with_seed(1).search(&mut game.clone(), &mut SharedTable::with_policy(1 << 20, ReplacementPolicy::TwoTier));
+
+        for threads in [1, 3] {
+            let table = SharedTable::with_policy(1 << 20, ReplacementPolicy::TwoTier);
+            let (col, score) = search(&game, &table, threads, &CancelToken::new(), None).unwrap();
+            assert!(game.is_move_valid(col));
+            assert_eq!(score, expected, "{} threads", threads);
+        }
+    }
+
+    #[test]
+    fn solve_agrees_with_a_single_thread() {
+        let tablebase = Tablebase::generate(5, 4);
+        //kept between positions like the book builder does
+        let table = SharedTable::with_policy(1 << 20, ReplacementPolicy::TwoTier);
+
+        for (game, _) in tablebase.positions().filter(|(game, _)| game.get_num_moves() == 4).step_by(5).take(6) {
+            let expected = AIGame::with_seed(1).solve(&mut game.clone(), &mut SharedTable::with_policy(1 << 20, ReplacementPolicy::TwoTier));
+            assert_eq!(solve(&game, &table, 3, &CancelToken::new(), None), Ok(expected), "key {}", game.get_unique_key());
+        }
+    }
+
+    #[test]
+    fn cancelled_search_still_answers() {
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let (col, _) = search(&BitBoard::with_size(5, 4), &SharedTable::with_policy(1 << 20, ReplacementPolicy::TwoTier), 2, &cancel, None).unwrap();
+        assert!(col < 5);
+    }
+}
@@ -0,0 +1,411 @@
+use crate::scripts::bit_board::{BitBoard};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Rollout {
+    Random, //uniformly random moves until the game ends
+    Heuristic, //takes immediate wins and blocks immediate losses, otherwise random
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct MctsConfig {
+    pub iterations: usize, //playouts per move
+    pub exploration: f64, //UCT exploration constant
+    pub rollout: Rollout,
+    pub seed: u64,
+}
+
+impl MctsConfig {
+    pub fn new(iterations: usize) -> Self {
+        Self {
+            iterations,
+            exploration: 1.4,
+            rollout: Rollout::Heuristic,
+            seed: 0,
+        }
+    }
+}
+
+struct Node {
+    col: usize, //move that led to this node
+    key: u64, //position after the move, used to find the node again on the next turn
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<usize>, //valid moves without a child yet
+    visits: u32,
+    reward: f64, //total reward for the player who made the move, 1 for a win and 0.5 for a tie
+    terminal: Option<f64>, //reward when the move ended the game
+}
+
+pub struct MctsPlayer {
+    pub config: MctsConfig,
+    rng: StdRng,
+    nodes: Vec<Node>, //tree stored as an arena, nodes[root] is the current position
+    root: usize,
+    done: usize, //iterations run for the current move
+    decided: Option<usize>, //immediate win of the current move, played without any iteration
+}
+
+impl MctsPlayer {
+    pub fn new(config: MctsConfig) -> Self {
+        Self {
+            config,
+            rng: StdRng::seed_from_u64(config.seed),
+            nodes: Vec::new(),
+            root: 0,
+            done: 0,
+            decided: None,
+        }
+    }
+
+    //runs the configured number of iterations and picks the most visited move,
+    //the tree is kept so the next call can continue from the position reached.
+    //None on a full board, or when no iteration was run
+    pub fn best_move(&mut self, game: &BitBoard) -> Option<usize> {
+        self.start(game);
+        self.search_step(game, self.config.iterations);
+        return self.chosen_move();
+    }
+
+    //moves the tree to the position of a new move, an immediate win is taken without any iteration
+    pub fn start(&mut self, game: &BitBoard) {
+        self.reuse_tree(game);
+        self.done = 0;
+        self.decided = (0..game.width()).find(|&col| game.is_move_valid(col) && game.is_winning_move(col));
+    }
+
+    //runs up to count of the iterations left for the current move, starting a new move if game is
+    //another position. returns true once all of them are done
+    pub fn search_step(&mut self, game: &BitBoard, count: usize) -> bool {
+        if self.nodes.get(self.root).map(|node| node.key) != Some(game.get_unique_key()) {
+            self.start(game);
+        }
+
+        if self.decided.is_some() {
+            return true;
+        }
+
+        let count = count.min(self.config.iterations.saturating_sub(self.done));
+
+        for _ in 0..count {
+            self.iterate(game);
+        }
+
+        self.done += count;
+        return self.done >= self.config.iterations;
+    }
+
+    //iterations run for the current move
+    pub fn completed(&self) -> usize {
+        self.done
+    }
+
+    //the immediate win, or the move with the most playouts so far. None if there was none
+    pub fn chosen_move(&self) -> Option<usize> {
+        if self.decided.is_some() {
+            return self.decided;
+        }
+
+        let best = self.nodes.get(self.root)?.children.iter()
+            .copied()
+            .max_by_key(|&child| self.nodes[child].visits)?;
+        return Some(self.nodes[best].col);
+    }
+
+    //number of playouts and average reward for each move tried from the current root
+    pub fn root_stats(&self) -> Vec<(usize, u32, f64)> {
+        if self.nodes.is_empty() {
+            return Vec::new();
+        }
+
+        self.nodes[self.root].children.iter()
+            .map(|&child| {
+                let node = &self.nodes[child];
+                (node.col, node.visits, node.reward / node.visits.max(1) as f64)
+            })
+            .collect()
+    }
+
+    //keeps the existing tree if the position is at most two moves below the root
+    //(our last move and the reply to it), otherwise starts over
+    fn reuse_tree(&mut self, game: &BitBoard) {
+        let key = game.get_unique_key();
+
+        if !self.nodes.is_empty() {
+            if self.nodes[self.root].key == key {
+                return;
+            }
+
+            let found = self.nodes[self.root].children.iter()
+                .flat_map(|&child| std::iter::once(child).chain(self.nodes[child].children.iter().copied()))
+                .find(|&node| self.nodes[node].key == key);
+
+            if let Some(node) = found {
+                self.reroot(node);
+                return;
+            }
+        }
+
+        self.nodes = vec![Node {
+            col: 0,
+            key,
+            parent: None,
+            children: Vec::new(),
+            untried: Self::valid_moves(game),
+            visits: 0,
+            reward: 0.0,
+            terminal: None,
+        }];
+        self.root = 0;
+    }
+
+    //copies the subtree under new_root into a fresh arena so the rest of the tree is freed
+    fn reroot(&mut self, new_root: usize) {
+        let mut old = std::mem::take(&mut self.nodes);
+        let mut stack = vec![(new_root, None)];
+
+        while let Some((idx, parent)) = stack.pop() {
+            let new_idx = self.nodes.len();
+            let children = std::mem::take(&mut old[idx].children);
+            let node = &old[idx];
+
+            self.nodes.push(Node {
+                col: node.col,
+                key: node.key,
+                parent,
+                children: Vec::with_capacity(children.len()),
+                untried: node.untried.clone(),
+                visits: node.visits,
+                reward: node.reward,
+                terminal: node.terminal,
+            });
+
+            if let Some(parent) = parent {
+                self.nodes[parent].children.push(new_idx);
+            }
+
+            for child in children {
+                stack.push((child, Some(new_idx)));
+            }
+        }
+
+        self.root = 0;
+    }
+
+    //one round of selection, expansion, rollout and backpropagation
+    fn iterate(&mut self, game: &BitBoard) {
+        let mut board = game.clone();
+        let mut node = self.root;
+
+        while self.nodes[node].terminal.is_none() && self.nodes[node].untried.is_empty() && !self.nodes[node].children.is_empty() {
+            node = self.select_child(node);
+            board.play_move(self.nodes[node].col);
+        }
+
+        if self.nodes[node].terminal.is_none() && !self.nodes[node].untried.is_empty() {
+            node = self.expand(node, &mut board);
+        }
+
+        let reward = match self.nodes[node].terminal {
+            Some(reward) => reward,
+            None => self.rollout(&mut board),
+        };
+
+        self.backpropagate(node, reward);
+    }
+
+    fn select_child(&self, node: usize) -> usize {
+        let log_visits = (self.nodes[node].visits.max(1) as f64).ln();
+        let mut best = self.nodes[node].children[0];
+        let mut best_value = f64::MIN;
+
+        for &child in &self.nodes[node].children {
+            let stats = &self.nodes[child];
+            let visits = stats.visits.max(1) as f64;
+            let value = stats.reward / visits + self.config.exploration * (log_visits / visits).sqrt();
+
+            if value > best_value {
+                best = child;
+                best_value = value;
+            }
+        }
+
+        return best;
+    }
+
+    fn expand(&mut self, node: usize, board: &mut BitBoard) -> usize {
+        let pick = self.rng.gen_range(0..self.nodes[node].untried.len());
+        let col = self.nodes[node].untried.swap_remove(pick);
+
+        let terminal = if board.is_winning_move(col) {
+            Some(1.0)
+        } else if board.is_draw() {
+            Some(0.5)
+        } else {
+            None
+        };
+
+        board.play_move(col);
+        let child = self.nodes.len();
+
+        self.nodes.push(Node {
+            col,
+            key: board.get_unique_key(),
+            parent: Some(node),
+            children: Vec::new(),
+            untried: if terminal.is_some() {Vec::new()} else {Self::valid_moves(board)},
+            visits: 0,
+            reward: 0.0,
+            terminal,
+        });
+        self.nodes[node].children.push(child);
+
+        return child;
+    }
+
+    //plays the game out and returns the reward for the player who moved last before the rollout
+    fn rollout(&mut self, board: &mut BitBoard) -> f64 {
+        let mut opponent_to_move = true;
+
+        loop {
+            let moves = Self::valid_moves(board);
+
+            if moves.is_empty() {
+                return 0.5;
+            }
+
+            let random = moves[self.rng.gen_range(0..moves.len())];
+            let col = match self.config.rollout {
+                Rollout::Random => random,
+                Rollout::Heuristic => {
+                    if let Some(&win) = moves.iter().find(|&&c| board.is_winning_move(c)) {
+                        win
+                    } else if let Some(&block) = moves.iter().find(|&&c| board.is_opponent_winning_move(c)) {
+                        block
+                    } else {
+                        random
+                    }
+                }
+            };
+
+            if board.is_winning_move(col) {
+                return if opponent_to_move {0.0} else {1.0};
+            }
+
+            if board.is_draw() {
+                return 0.5;
+            }
+
+            board.play_move(col);
+            opponent_to_move = !opponent_to_move;
+        }
+    }
+
+    fn backpropagate(&mut self, mut node: usize, mut reward: f64) {
+        loop {
+            self.nodes[node].visits += 1;
+            self.nodes[node].reward += reward;
+
+            match self.nodes[node].parent {
+                Some(parent) => {
+                    node = parent;
+                    reward = 1.0 - reward;
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn valid_moves(board: &BitBoard) -> Vec<usize> {
+        (0..board.width()).filter(|&col| board.is_move_valid(col)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(game: &mut BitBoard, line: &str) {
+        for c in line.chars() {
+            game.play_move(c.to_digit(10).unwrap() as usize);
+        }
+    }
+
+    fn player(iterations: usize, rollout: Rollout) -> MctsPlayer {
+        let mut config = MctsConfig::new(iterations);
+        config.rollout = rollout;
+        MctsPlayer::new(config)
+    }
+
+    #[test]
+    fn takes_an_immediate_win() {
+        let mut game = BitBoard::new();
+        play(&mut game, "001122");
+
+        for rollout in [Rollout::Random, Rollout::Heuristic] {
+            assert_eq!(player(0, rollout).best_move(&game), Some(3));
+        }
+    }
+
+    #[test]
+    fn blocks_an_immediate_loss() {
+        let mut game = BitBoard::new();
+        play(&mut game, "0011226");
+
+        for rollout in [Rollout::Random, Rollout::Heuristic] {
+            assert_eq!(player(5000, rollout).best_move(&game), Some(3), "{:?}", rollout);
+        }
+    }
+
+    #[test]
+    fn no_move_without_iterations_or_room() {
+        assert_eq!(player(0, Rollout::Heuristic).best_move(&BitBoard::new()), None);
+
+        //a drawn 4x1 board has no columns left
+        let mut game = BitBoard::with_size(4, 1);
+        play(&mut game, "0123");
+        assert_eq!(player(100, Rollout::Heuristic).best_move(&game), None);
+    }
+
+    #[test]
+    fn stats_follow_the_position() {
+        let mut mcts = player(2000, Rollout::Heuristic);
+        let mut game = BitBoard::new();
+        mcts.best_move(&game);
+        assert_eq!(mcts.root_stats().iter().map(|&(_, visits, _)| visits).sum::<u32>(), 2000);
+
+        //the stats of the previous root are not reported for a position won at once
+        play(&mut game, "001122");
+        assert_eq!(mcts.best_move(&game), Some(3));
+        assert!(mcts.root_stats().is_empty());
+    }
+
+    #[test]
+    fn same_seed_same_moves_on_small_boards() {
+        let mut game = BitBoard::with_size(5, 4);
+        play(&mut game, "22");
+        let first = player(3000, Rollout::Random).best_move(&game);
+        let second = player(3000, Rollout::Random).best_move(&game);
+
+        assert!(first.is_some_and(|col| col < 5));
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn steps_add_up_to_one_search() {
+        let mut game = BitBoard::new();
+        play(&mut game, "3322");
+        let mut stepped = player(2000, Rollout::Heuristic);
+        stepped.start(&game);
+        let mut steps = 0;
+
+        while !stepped.search_step(&game, 300) {
+            steps += 1;
+            assert_eq!(stepped.completed(), steps * 300);
+        }
+
+        assert_eq!(stepped.completed(), 2000);
+        assert_eq!(stepped.chosen_move(), player(2000, Rollout::Heuristic).best_move(&game));
+    }
+}
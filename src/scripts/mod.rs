@@ -1,4 +1,17 @@
 pub mod array_board;
 pub mod ai;
+pub mod analysis;
 pub mod bit_board;
+pub mod evaluation;
+pub mod heuristic;
+pub mod hint;
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+pub mod lazy_smp;
+pub mod mcts;
+pub mod ntuple;
+pub mod opening_book;
+pub mod player;
+pub mod pn_search;
+pub mod search_control;
+pub mod tablebase;
 pub mod trans_table;
\ No newline at end of file
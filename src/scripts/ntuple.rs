@@ -0,0 +1,302 @@
+use crate::scripts::bit_board::{BitBoard};
+use crate::scripts::heuristic::{Evaluator};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+//file layout: magic, version, width, height, weight count (u32 LE), then every weight as an f32 LE
+const MAGIC: &[u8; 4] = b"C4NT";
+const VERSION: u8 = 1;
+const HEADER_SIZE: usize = 11;
+
+const TUPLE_LENGTH: usize = 4;
+//a cell is empty out of reach, empty and playable, the mover's or the opponent's
+const CELL_STATES: usize = 4;
+const TUPLE_WEIGHTS: usize = 256; //CELL_STATES ^ TUPLE_LENGTH
+const EVALUATION_SCALE: f32 = 1000.0; //network output in [-1, 1] times this, far below heuristic::WIN_SCORE
+
+//training settings for NTupleNetwork::train
+#[derive(Copy, Clone, Debug)]
+pub struct TrainingConfig {
+    pub games: usize, //self-play games
+    pub learning_rate: f32,
+    pub exploration: f64, //chance of a random move instead of the best one
+    pub seed: u64,
+}
+
+impl TrainingConfig {
+    pub fn new(games: usize) -> Self {
+        Self {
+            games,
+            learning_rate: 0.01,
+            exploration: 0.1,
+            seed: 0,
+        }
+    }
+}
+
+//evaluation learned by self-play: every line of four cells on the board is a tuple,
+//and each pattern the tuple can show has its own weight
+pub struct NTupleNetwork {
+    width: usize,
+    height: usize,
+    tuples: Vec<[u64; TUPLE_LENGTH]>, //single cell masks, one array per line
+    weights: Vec<f32>, //TUPLE_WEIGHTS per tuple
+}
+
+impl NTupleNetwork {
+    pub fn new(width: usize, height: usize) -> Self {
+        let tuples = lines(width, height);
+        let weights = vec![0.0; tuples.len() * TUPLE_WEIGHTS];
+
+        Self {
+            width,
+            height,
+            tuples,
+            weights,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    //expected result of a running game for the player to move, from -1 (loss) to 1 (win)
+    pub fn value(&self, game: &BitBoard) -> f32 {
+        let sum: f32 = self.features(game).map(|idx| self.weights[idx]).sum();
+        sum.tanh()
+    }
+
+    //temporal difference learning over games the network plays against itself:
+    //after each move the position is pulled towards the value of the position that follows
+    pub fn train<F: FnMut(usize)>(&mut self, config: &TrainingConfig, mut on_game: F) {
+        let mut rng = StdRng::seed_from_u64(config.seed);
+
+        for number in 0..config.games {
+            let mut game = BitBoard::with_size(self.width, self.height);
+
+            loop {
+                let moves: Vec<usize> = (0..self.width).filter(|&col| game.is_move_valid(col)).collect();
+                let values: Vec<f32> = moves.iter().map(|&col| self.move_value(&mut game, col)).collect();
+                let best = (0..moves.len()).fold(0, |best, i| if values[i] > values[best] {i} else {best});
+                let chosen = if rng.gen_bool(config.exploration) {rng.gen_range(0..moves.len())} else {best};
+
+                self.update(&game, values[best], config.learning_rate);
+                let col = moves[chosen];
+
+                if game.is_winning_move(col) || game.get_num_moves() + 1 == self.width * self.height {
+                    break;
+                }
+
+                game.play_move(col);
+            }
+
+            on_game(number + 1);
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + 4 * self.weights.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.push(self.width as u8);
+        bytes.push(self.height as u8);
+        bytes.extend_from_slice(&(self.weights.len() as u32).to_le_bytes());
+
+        for weight in &self.weights {
+            bytes.extend_from_slice(&weight.to_le_bytes());
+        }
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < HEADER_SIZE || &bytes[0..4] != MAGIC {
+            return Err("Not a weights file!".to_string());
+        }
+
+        if bytes[4] != VERSION {
+            return Err(format!("Unsupported weights version {}", bytes[4]));
+        }
+
+        let (width, height) = (bytes[5] as usize, bytes[6] as usize);
+
+        if !BitBoard::is_size_supported(width, height) {
+            return Err(format!("Invalid board size {}x{}", width, height));
+        }
+
+        let mut network = Self::new(width, height);
+        let count = u32::from_le_bytes([bytes[7], bytes[8], bytes[9], bytes[10]]) as usize;
+
+        if count != network.weights.len() || bytes.len() != HEADER_SIZE + 4 * count {
+            return Err("Weights do not match the board size!".to_string());
+        }
+
+        for (weight, chunk) in network.weights.iter_mut().zip(bytes[HEADER_SIZE..].chunks_exact(4)) {
+            *weight = f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+
+        Ok(network)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: &str) -> Result<Self, String> {
+        let bytes = std::fs::read(path).map_err(|err| err.to_string())?;
+        Self::from_bytes(&bytes)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        std::fs::write(path, self.to_bytes()).map_err(|err| err.to_string())
+    }
+
+    //value of playing col for the player to move
+    fn move_value(&self, game: &mut BitBoard, col: usize) -> f32 {
+        if game.is_winning_move(col) {
+            return 1.0;
+        }
+
+        if game.get_num_moves() + 1 == self.width * self.height {
+            return 0.0;
+        }
+
+        game.play_move(col);
+        let value = -self.value(game);
+        let _ = game.undo_move(col);
+
+        return value;
+    }
+
+    //one gradient step moving the value of game towards target
+    fn update(&mut self, game: &BitBoard, target: f32, learning_rate: f32) {
+        let value = self.value(game);
+        let step = learning_rate * (target - value) * (1.0 - value * value);
+        let features: Vec<usize> = self.features(game).collect();
+
+        for idx in features {
+            self.weights[idx] += step;
+        }
+    }
+
+    //index of the active weight of every tuple
+    fn features<'a>(&'a self, game: &BitBoard) -> impl Iterator<Item = usize> + 'a {
+        let current = game.current_position();
+        let opponent = game.opponent_position();
+        let playable = game.get_height_mask() & game.board_mask();
+
+        self.tuples.iter().enumerate().map(move |(t, cells)| {
+            let pattern = cells.iter().fold(0, |pattern, &cell| {
+                let state = if current & cell != 0 {
+                    2
+                } else if opponent & cell != 0 {
+                    3
+                } else if playable & cell != 0 {
+                    1
+                } else {
+                    0
+                };
+                pattern * CELL_STATES + state
+            });
+            t * TUPLE_WEIGHTS + pattern
+        })
+    }
+}
+
+impl Evaluator for NTupleNetwork {
+    fn evaluate(&self, game: &BitBoard) -> i64 {
+        (self.value(game) * EVALUATION_SCALE) as i64
+    }
+}
+
+//every horizontal, vertical and diagonal line of four cells, as single cell masks
+fn lines(width: usize, height: usize) -> Vec<[u64; TUPLE_LENGTH]> {
+    let cell = |col: i64, row: i64| 1u64 << (col as usize * (height + 1) + row as usize);
+    let inside = |col: i64, row: i64| col >= 0 && row >= 0 && (col as usize) < width && (row as usize) < height;
+    let mut lines = Vec::new();
+
+    for col in 0..width as i64 {
+        for row in 0..height as i64 {
+            for (dc, dr) in [(1, 0), (0, 1), (1, 1), (1, -1)] {
+                let end = (col + dc * (TUPLE_LENGTH as i64 - 1), row + dr * (TUPLE_LENGTH as i64 - 1));
+
+                if inside(end.0, end.1) {
+                    let mut line = [0; TUPLE_LENGTH];
+
+                    for i in 0..TUPLE_LENGTH as i64 {
+                        line[i as usize] = cell(col + dc * i, row + dr * i);
+                    }
+
+                    lines.push(line);
+                }
+            }
+        }
+    }
+
+    return lines;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(game: &mut BitBoard, line: &str) {
+        for c in line.chars() {
+            game.play_move(c.to_digit(10).unwrap() as usize);
+        }
+    }
+
+    fn trained(games: usize) -> NTupleNetwork {
+        let mut network = NTupleNetwork::new(4, 4);
+        let mut config = TrainingConfig::new(games);
+        config.seed = 1;
+        network.train(&config, |_| {});
+        network
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let network = trained(20);
+        let copy = NTupleNetwork::from_bytes(&network.to_bytes()).unwrap();
+
+        assert_eq!((copy.width(), copy.height()), (4, 4));
+        assert_eq!(copy.weights, network.weights);
+        assert!(network.weights.iter().any(|&weight| weight != 0.0));
+    }
+
+    #[test]
+    fn broken_files_are_rejected() {
+        let bytes = NTupleNetwork::new(4, 4).to_bytes();
+        let broken = |idx: usize, byte: u8| {
+            let mut bytes = bytes.clone();
+            bytes[idx] = byte;
+            NTupleNetwork::from_bytes(&bytes)
+        };
+
+        assert!(broken(0, b'X').is_err());
+        assert!(broken(4, VERSION + 1).is_err());
+        //too tall for the diagonal shifts, then weights made for another size
+        assert!(broken(6, 20).is_err());
+        assert!(broken(5, 5).is_err());
+        assert!(NTupleNetwork::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn training_learns_to_see_a_win() {
+        //the player to move wins by completing column 0
+        let mut game = BitBoard::with_size(4, 4);
+        play(&mut game, "010101");
+        assert!(game.is_winning_move(0));
+
+        let untrained = NTupleNetwork::new(4, 4);
+        let network = trained(5000);
+        assert!(network.value(&game) > untrained.value(&game) + 0.3, "value {}", network.value(&game));
+
+        //one move earlier the opponent has to block that column
+        let _ = game.undo_move(1);
+        let best = (0..4).max_by(|&a, &b| network.move_value(&mut game, a).total_cmp(&network.move_value(&mut game, b)));
+        assert_eq!(best, Some(0));
+    }
+}
@@ -0,0 +1,372 @@
+use crate::scripts::bit_board::{BitBoard};
+use std::sync::{Arc, OnceLock};
+
+const HEIGHT: usize = 6;
+const WIDTH: usize = 7;
+
+//file layout: magic, version, width, height, max ply, entry count (u32 LE),
+//then one ENTRY_SIZE record per position sorted by canonical key
+const MAGIC: &[u8; 4] = b"C4BK";
+const VERSION: u8 = 1;
+const HEADER_SIZE: usize = 12;
+const ENTRY_SIZE: usize = 10;
+
+//positions past max_ply where the search plays poorly, written as one sequence of columns
+//from the empty board; every other move order filling the same cells is booked as well
+const EXTRA_LINES: [&str; 2] = [
+    "3333334",
+    "333333454",
+];
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct BookEntry {
+    pub key: u64, //canonical key of the position
+    pub column: u8, //best column in the canonical orientation
+    pub score: i8, //score of the position for the player to move
+}
+
+pub struct OpeningBook {
+    entries: Vec<BookEntry>, //sorted by key for binary search
+    max_ply: usize,
+}
+
+impl OpeningBook {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            max_ply: 0,
+        }
+    }
+
+    //book shipped inside the binary, generated with the build-book command
+    pub fn embedded() -> Self {
+        Self::from_bytes(include_bytes!("opening_book.bin")).unwrap_or_else(|_| Self::new())
+    }
+
+    //embedded book parsed once, every engine and search thread reads the same copy
+    pub fn shared() -> Arc<Self> {
+        static BOOK: OnceLock<Arc<OpeningBook>> = OnceLock::new();
+        BOOK.get_or_init(|| Arc::new(Self::embedded())).clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn max_ply(&self) -> usize {
+        self.max_ply
+    }
+
+    pub fn get(&self, key: u64) -> Option<BookEntry> {
+        self.entries
+            .binary_search_by_key(&key, |entry| entry.key)
+            .ok()
+            .map(|idx| self.entries[idx])
+    }
+
+    //best column for the player to move, mirrored back if the book stores the flipped position
+    pub fn get_move(&self, game: &BitBoard) -> Option<usize> {
+        if game.width() != WIDTH || game.height() != HEIGHT {
+            return None;
+        }
+
+        let key = game.get_unique_key();
+        let mirrored = game.get_mirrored_key();
+        let entry = self.get(key.min(mirrored))?;
+        let col = entry.column as usize;
+
+        if mirrored < key {
+            Some(WIDTH - 1 - col)
+        } else {
+            Some(col)
+        }
+    }
+
+    pub fn insert(&mut self, game: &BitBoard, col: usize, score: i64) {
+        let key = game.get_unique_key();
+        let mirrored = game.get_mirrored_key();
+        let column = if mirrored < key {WIDTH - 1 - col} else {col};
+        let entry = BookEntry {
+            key: key.min(mirrored),
+            column: column as u8,
+            score: score as i8,
+        };
+
+        match self.entries.binary_search_by_key(&entry.key, |entry| entry.key) {
+            Ok(idx) => self.entries[idx] = entry,
+            Err(idx) => self.entries.insert(idx, entry),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + ENTRY_SIZE * self.entries.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.push(WIDTH as u8);
+        bytes.push(HEIGHT as u8);
+        bytes.push(self.max_ply as u8);
+        bytes.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+
+        for entry in &self.entries {
+            bytes.extend_from_slice(&entry.key.to_le_bytes());
+            bytes.push(entry.column);
+            bytes.push(entry.score as u8);
+        }
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < HEADER_SIZE || &bytes[0..4] != MAGIC {
+            return Err("Not an opening book file!".to_string());
+        }
+
+        if bytes[4] != VERSION {
+            return Err(format!("Unsupported opening book version {}", bytes[4]));
+        }
+
+        if bytes[5] as usize != WIDTH || bytes[6] as usize != HEIGHT {
+            return Err(format!("Opening book is for a {}x{} board", bytes[5], bytes[6]));
+        }
+
+        let count = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize;
+
+        if bytes.len() != HEADER_SIZE + count * ENTRY_SIZE {
+            return Err("Opening book is truncated!".to_string());
+        }
+
+        let mut entries = Vec::with_capacity(count);
+
+        for record in bytes[HEADER_SIZE..].chunks_exact(ENTRY_SIZE) {
+            let mut key = [0; 8];
+            key.copy_from_slice(&record[0..8]);
+            entries.push(BookEntry {
+                key: u64::from_le_bytes(key),
+                column: record[8],
+                score: record[9] as i8,
+            });
+        }
+
+        if entries.windows(2).any(|pair| pair[0].key >= pair[1].key) {
+            return Err("Opening book entries are not sorted!".to_string());
+        }
+
+        Ok(Self {
+            entries,
+            max_ply: bytes[7] as usize,
+        })
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        std::fs::write(path, self.to_bytes()).map_err(|err| err.to_string())
+    }
+
+    //solves every position reachable in at most max_ply moves, plus the extra lines;
+    //solve must return the exact best column and score for the player to move,
+    //it is only called at max_ply and shallower positions are backed up from their children
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn build<F>(max_ply: usize, mut solve: F) -> Self
+    where
+        F: FnMut(&mut BitBoard) -> (usize, i64),
+    {
+        let mut book = Self::new();
+        let mut game = BitBoard::new();
+        book.max_ply = max_ply;
+        book.visit(&mut solve, &mut game, max_ply);
+
+        for line in EXTRA_LINES.iter() {
+            let mut target = BitBoard::new();
+
+            for c in line.chars() {
+                target.play_move(c.to_digit(10).unwrap() as usize);
+            }
+
+            let mut game = BitBoard::new();
+            book.visit_orders(&mut solve, &mut game, target.total_mask);
+        }
+
+        book
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn visit<F>(&mut self, solve: &mut F, game: &mut BitBoard, plies_left: usize)
+    where
+        F: FnMut(&mut BitBoard) -> (usize, i64),
+    {
+        if self.get(game.get_canonical_key()).is_some() {
+            return;
+        }
+
+        if plies_left == 0 {
+            let (best_move, best_score) = solve(game);
+            self.insert(game, best_move, best_score);
+            return;
+        }
+
+        if let Some(col) = (0..WIDTH).find(|&col| game.is_move_valid(col) && game.is_winning_move(col)) {
+            self.insert(game, col, ((WIDTH * HEIGHT + 1 - game.get_num_moves()) / 2) as i64);
+        }
+
+        let mut best: Option<(usize, i64)> = None;
+
+        for col in 0..WIDTH {
+            //positions after a winning move are over and never looked up
+            if game.is_move_valid(col) && !game.is_winning_move(col) {
+                game.play_move(col);
+                self.visit(solve, game, plies_left - 1);
+                let score = -self.get(game.get_canonical_key()).unwrap().score as i64;
+                let _ = game.undo_move(col);
+
+                if best.is_none_or(|(_, best_score)| score > best_score) {
+                    best = Some((col, score));
+                }
+            }
+        }
+
+        //an immediate win is already booked, and a full board is never reached this early
+        if self.get(game.get_canonical_key()).is_none() {
+            let (col, score) = best.unwrap();
+            self.insert(game, col, score);
+        }
+    }
+
+    //solves every position whose occupied cells are exactly target, whatever order they were filled in
+    #[cfg(not(target_arch = "wasm32"))]
+    fn visit_orders<F>(&mut self, solve: &mut F, game: &mut BitBoard, target: u64)
+    where
+        F: FnMut(&mut BitBoard) -> (usize, i64),
+    {
+        if game.total_mask == target {
+            if self.get(game.get_canonical_key()).is_none() {
+                let (best_move, best_score) = solve(game);
+                self.insert(game, best_move, best_score);
+            }
+            return;
+        }
+
+        for col in 0..WIDTH {
+            if game.is_move_valid(col) && game.move_mask(col) & target != 0 && !game.is_winning_move(col) {
+                game.play_move(col);
+                self.visit_orders(solve, game, target);
+                let _ = game.undo_move(col);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(line: &str) -> BitBoard {
+        let mut game = BitBoard::new();
+
+        for c in line.chars() {
+            game.play_move(c.to_digit(10).unwrap() as usize);
+        }
+
+        return game;
+    }
+
+    #[test]
+    fn embedded_book_opens_in_the_centre() {
+        let book = OpeningBook::embedded();
+        let game = BitBoard::new();
+
+        //the first player wins with the centre column on the last move
+        assert_eq!(book.get_move(&game), Some(3));
+        assert_eq!(book.get(game.get_canonical_key()).unwrap().score, 1);
+    }
+
+    #[test]
+    fn embedded_book_backs_up_its_leaves() {
+        let book = OpeningBook::embedded();
+        let mut stack = vec![BitBoard::new()];
+
+        while let Some(mut game) = stack.pop() {
+            if game.get_num_moves() == book.max_ply() {
+                assert!(book.get(game.get_canonical_key()).is_some());
+                continue;
+            }
+
+            let entry = book.get(game.get_canonical_key()).unwrap();
+            let mut best = i64::MIN;
+
+            for col in 0..WIDTH {
+                if !game.is_move_valid(col) {
+                    continue;
+                }
+
+                let score = if game.is_winning_move(col) {
+                    ((WIDTH * HEIGHT + 1 - game.get_num_moves()) / 2) as i64
+                } else {
+                    game.play_move(col);
+                    let score = -book.get(game.get_canonical_key()).unwrap().score as i64;
+                    stack.push(game.clone());
+                    let _ = game.undo_move(col);
+                    score
+                };
+
+                best = best.max(score);
+            }
+
+            assert_eq!(entry.score as i64, best);
+
+            //the booked column reaches the score
+            let col = book.get_move(&game).unwrap();
+            if !game.is_winning_move(col) {
+                game.play_move(col);
+                assert_eq!(-book.get(game.get_canonical_key()).unwrap().score as i64, best);
+            }
+        }
+    }
+
+    #[test]
+    fn extra_lines_cover_every_move_order() {
+        let book = OpeningBook::embedded();
+
+        //the same cells as "3333334" with the other colouring
+        for line in ["3333334", "4333333", "3343333", "333333454", "433333345", "335433334"] {
+            assert!(book.get_move(&play(line)).is_some(), "{} is not booked", line);
+        }
+    }
+
+    #[test]
+    fn build_backs_up_the_leaf_scores() {
+        let mut leaves = 0;
+        //pretend a stone in the centre column loses for the player to move, anything else draws
+        let book = OpeningBook::build(1, |game| {
+            leaves += 1;
+            let centre = game.total_mask & game.full_col_mask(3) != 0;
+            (0, if centre {-1} else {0})
+        });
+
+        let root = BitBoard::new();
+        assert!(leaves >= 4);
+        assert_eq!(book.max_ply(), 1);
+        assert_eq!(book.get_move(&root), Some(3));
+        assert_eq!(book.get(root.get_canonical_key()).unwrap().score, 1);
+        assert_eq!(book.get(play("3").get_canonical_key()).unwrap().score, -1);
+        assert_eq!(book.get(play("5").get_canonical_key()).unwrap().score, 0);
+    }
+
+    #[test]
+    fn engines_share_one_book() {
+        assert!(Arc::ptr_eq(&OpeningBook::shared(), &OpeningBook::shared()));
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let mut book = OpeningBook::new();
+        book.insert(&play("33"), 2, 1);
+        book.insert(&play("0"), 3, -2);
+
+        let copy = OpeningBook::from_bytes(&book.to_bytes()).unwrap();
+        assert_eq!(copy.len(), 2);
+        assert_eq!(copy.get_move(&play("33")), Some(2));
+        assert_eq!(copy.get_move(&play("6")), Some(3));
+        assert_eq!(copy.get(play("0").get_canonical_key()).unwrap().score, -2);
+        assert!(OpeningBook::from_bytes(&book.to_bytes()[..20]).is_err());
+    }
+}
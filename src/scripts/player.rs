@@ -0,0 +1,382 @@
+use crate::scripts::ai::{AIGame, RootSearch};
+use crate::scripts::bit_board::{BitBoard};
+use crate::scripts::heuristic::{self, Evaluator, Handcrafted};
+use crate::scripts::mcts::{MctsPlayer};
+use crate::scripts::search_control::{CancelToken};
+use crate::scripts::trans_table::{TranspositionTable};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::time::Duration;
+
+const TABLE_BYTES: usize = 16 << 20; //small enough for the browser
+const MCTS_STEP: usize = 1000; //playouts per thinking step
+
+//anything that picks moves: a search engine or a person clicking on the board.
+//moves are made in steps so the browser can stay responsive while an engine thinks
+pub trait Player {
+    fn name(&self) -> String;
+
+    //called once before each move, budget is how long the move may take
+    fn start_move(&mut self, _game: &BitBoard, _budget: Duration) {}
+
+    //does a slice of work on the move, returns the column once decided
+    fn think(&mut self, game: &BitBoard) -> Option<usize>;
+
+    //column to play right away when the budget runs out, None if the player cannot be hurried
+    fn move_now(&mut self, _game: &BitBoard) -> Option<usize> {
+        None
+    }
+
+    //column picked by a person, engines ignore it
+    fn input(&mut self, _col: usize) {}
+
+    fn is_human(&self) -> bool {
+        false
+    }
+
+    //what the player is busy with, for display while it thinks
+    fn status(&self) -> Option<String> {
+        None
+    }
+
+    //forgets the previous game, engines may keep what they learned from it
+    fn new_game(&mut self) {}
+
+    //what the player learned that is worth keeping for later sessions, in at most max_bytes, None if nothing
+    fn save_memory(&self, _max_bytes: usize) -> Option<Vec<u8>> {
+        None
+    }
+
+    //takes back what save_memory returned in an earlier session
+    fn load_memory(&mut self, _bytes: &[u8]) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+//asks player for a move and waits for it, None for a person who has not given any input yet
+#[cfg(not(target_arch = "wasm32"))]
+pub fn choose_move(player: &mut dyn Player, game: &BitBoard, budget: Duration) -> Option<usize> {
+    let deadline = std::time::Instant::now().checked_add(budget);
+    player.start_move(game, budget);
+
+    loop {
+        if let Some(col) = player.think(game) {
+            return Some(col);
+        }
+
+        if player.is_human() {
+            return None;
+        }
+
+        if deadline.is_some_and(|deadline| std::time::Instant::now() >= deadline) {
+            return player.move_now(game);
+        }
+    }
+}
+
+//the negamax engine, searching a bounded number of nodes per step
+pub struct SolverPlayer {
+    ai: AIGame,
+    trans_table: TranspositionTable,
+    board: BitBoard, //position being searched
+    search: Option<RootSearch>,
+}
+
+impl SolverPlayer {
+    pub fn new(ai: AIGame) -> Self {
+        Self {
+            ai,
+            trans_table: TranspositionTable::with_budget(TABLE_BYTES),
+            board: BitBoard::new(),
+            search: None,
+        }
+    }
+}
+
+impl Player for SolverPlayer {
+    fn name(&self) -> String {
+        let swindle = if self.ai.swindle {", swindling"} else {""};
+        format!("Solver (seed {}{})", self.ai.seed(), swindle)
+    }
+
+    fn start_move(&mut self, game: &BitBoard, budget: Duration) {
+        //a fresh token per move, so a timer left over from an earlier move cannot stop this one
+        let cancel = CancelToken::new();
+        self.ai.set_cancel_token(cancel.clone());
+        cancel_after(cancel, budget);
+        self.board = game.clone();
+        self.trans_table.new_search();
+        self.search = Some(self.ai.start_search(game));
+    }
+
+    fn think(&mut self, _game: &BitBoard) -> Option<usize> {
+        let search = self.search.as_mut()?;
+
+        if self.ai.search_step(search, &mut self.board, &mut self.trans_table) {
+            let search = self.search.take()?;
+            return Some(self.ai.chosen_move(&search));
+        }
+
+        return None;
+    }
+
+    fn move_now(&mut self, _game: &BitBoard) -> Option<usize> {
+        let search = self.search.take()?;
+        Some(self.ai.chosen_move(&search))
+    }
+
+    fn status(&self) -> Option<String> {
+        let search = self.search.as_ref()?;
+        let progress = self.ai.progress();
+        let best = match progress.best_move {
+            Some(col) => format!(", best so far: column {}", col + 1),
+            None => String::new(),
+        };
+
+        Some(format!("Thinking... {}/{} columns, depth {}, {} nodes{}",
+            search.completed(), search.total(), progress.depth, progress.nodes, best))
+    }
+
+    //the table is kept, the same early positions come up game after game.
+    //start_move ages it, so entries from earlier games give way to current ones first
+    fn new_game(&mut self) {
+        self.search = None;
+    }
+
+    fn save_memory(&self, max_bytes: usize) -> Option<Vec<u8>> {
+        Some(self.trans_table.to_bytes(max_bytes))
+    }
+
+    fn load_memory(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.trans_table.load_bytes(bytes)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn cancel_after(cancel: CancelToken, budget: Duration) {
+    if budget != Duration::MAX {
+        std::thread::spawn(move || {
+            std::thread::sleep(budget);
+            cancel.cancel();
+        });
+    }
+}
+
+//threads are not available in the browser, there the caller uses move_now once time is up
+#[cfg(target_arch = "wasm32")]
+fn cancel_after(_cancel: CancelToken, _budget: Duration) {}
+
+//plays any valid column
+pub struct RandomPlayer {
+    rng: StdRng,
+}
+
+impl RandomPlayer {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Player for RandomPlayer {
+    fn name(&self) -> String {
+        "Random".to_string()
+    }
+
+    fn think(&mut self, game: &BitBoard) -> Option<usize> {
+        let moves: Vec<usize> = (0..game.width()).filter(|&col| game.is_move_valid(col)).collect();
+
+        if moves.is_empty() {
+            return None;
+        }
+
+        Some(moves[self.rng.gen_range(0..moves.len())])
+    }
+}
+
+//looks a fixed number of moves ahead and judges the positions it stops at with an evaluator
+pub struct HeuristicPlayer {
+    depth: usize,
+    rng: StdRng, //breaks ties between equally good moves
+    evaluator: Box<dyn Evaluator>,
+    label: String, //name of the evaluator
+}
+
+impl HeuristicPlayer {
+    pub fn new(depth: usize, seed: u64) -> Self {
+        Self::with_evaluator(depth, seed, Box::new(Handcrafted), "Heuristic")
+    }
+
+    pub fn with_evaluator(depth: usize, seed: u64, evaluator: Box<dyn Evaluator>, label: &str) -> Self {
+        Self {
+            depth,
+            rng: StdRng::seed_from_u64(seed),
+            evaluator,
+            label: label.to_string(),
+        }
+    }
+}
+
+impl Player for HeuristicPlayer {
+    fn name(&self) -> String {
+        format!("{} (depth {})", self.label, self.depth)
+    }
+
+    fn think(&mut self, game: &BitBoard) -> Option<usize> {
+        let scores = heuristic::move_scores(game, self.evaluator.as_ref(), self.depth);
+        let best = scores.iter().flatten().copied().max()?;
+        let candidates: Vec<usize> = (0..scores.len()).filter(|&col| scores[col] == Some(best)).collect();
+
+        Some(candidates[self.rng.gen_range(0..candidates.len())])
+    }
+}
+
+impl Player for MctsPlayer {
+    fn name(&self) -> String {
+        format!("MCTS ({} playouts)", self.config.iterations)
+    }
+
+    fn start_move(&mut self, game: &BitBoard, _budget: Duration) {
+        self.start(game);
+    }
+
+    //a slice of the playouts per step, so the browser stays responsive
+    fn think(&mut self, game: &BitBoard) -> Option<usize> {
+        if self.search_step(game, MCTS_STEP) {
+            return self.chosen_move();
+        }
+
+        return None;
+    }
+
+    fn move_now(&mut self, _game: &BitBoard) -> Option<usize> {
+        self.chosen_move()
+    }
+
+    fn status(&self) -> Option<String> {
+        Some(format!("Thinking... {}/{} playouts", self.completed(), self.config.iterations))
+    }
+}
+
+//waits for a column chosen on the board or the command line
+#[derive(Default)]
+pub struct HumanPlayer {
+    pending: Option<usize>,
+}
+
+impl HumanPlayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Player for HumanPlayer {
+    fn name(&self) -> String {
+        "Human".to_string()
+    }
+
+    fn think(&mut self, game: &BitBoard) -> Option<usize> {
+        self.pending.take().filter(|&col| col < game.width() && game.is_move_valid(col))
+    }
+
+    fn input(&mut self, col: usize) {
+        self.pending = Some(col);
+    }
+
+    fn is_human(&self) -> bool {
+        true
+    }
+
+    fn new_game(&mut self) {
+        self.pending = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scripts::mcts::{MctsConfig};
+    use crate::scripts::tablebase::{Tablebase};
+
+    fn play(line: &str) -> BitBoard {
+        let mut game = BitBoard::new();
+
+        for c in line.chars() {
+            game.play_move(c.to_digit(10).unwrap() as usize);
+        }
+
+        return game;
+    }
+
+    #[test]
+    fn random_player_only_plays_open_columns() {
+        let mut player = RandomPlayer::new(1);
+        let game = play("000000");
+        assert!((0..200).all(|_| player.think(&game).is_some_and(|col| col != 0 && game.is_move_valid(col))));
+
+        let mut full = BitBoard::with_size(4, 1);
+
+        for col in 0..4 {
+            full.play_move(col);
+        }
+
+        assert_eq!(player.think(&full), None);
+    }
+
+    #[test]
+    fn heuristic_player_wins_and_blocks() {
+        let mut player = HeuristicPlayer::new(2, 1);
+        assert_eq!(choose_move(&mut player, &play("010101"), Duration::MAX), Some(0));
+        assert_eq!(choose_move(&mut player, &play("01010"), Duration::MAX), Some(0));
+    }
+
+    #[test]
+    fn solver_player_matches_the_tablebase() {
+        let tablebase = Tablebase::generate(4, 4);
+        let mut player = SolverPlayer::new(AIGame::with_seed(1));
+
+        for (mut game, entry) in tablebase.positions().step_by(101) {
+            let col = choose_move(&mut player, &game, Duration::MAX).unwrap();
+            assert_eq!(tablebase.move_score(&mut game, col), Some(entry.score(&game)), "key {}", game.get_unique_key());
+        }
+    }
+
+    #[test]
+    fn solver_player_can_be_hurried() {
+        let mut player = SolverPlayer::new(AIGame::with_seed(1));
+        let game = play("33");
+        player.start_move(&game, Duration::MAX);
+        assert!(player.status().is_some());
+
+        let col = player.move_now(&game).unwrap();
+        assert!(game.is_move_valid(col));
+        assert_eq!(player.status(), None);
+    }
+
+    #[test]
+    fn mcts_player_takes_a_win() {
+        let mut player = MctsPlayer::new(MctsConfig::new(2000));
+        assert_eq!(choose_move(&mut player, &play("010101"), Duration::MAX), Some(0));
+    }
+
+    #[test]
+    fn human_player_waits_for_a_valid_column() {
+        let mut player = HumanPlayer::new();
+        let game = play("000000");
+        assert_eq!(choose_move(&mut player, &game, Duration::MAX), None);
+
+        player.input(0);
+        assert_eq!(player.think(&game), None);
+
+        player.input(9);
+        assert_eq!(player.think(&game), None);
+
+        player.input(3);
+        player.new_game();
+        assert_eq!(player.think(&game), None);
+
+        player.input(3);
+        assert_eq!(choose_move(&mut player, &game, Duration::MAX), Some(3));
+    }
+}
@@ -0,0 +1,329 @@
+use crate::scripts::ai::{Outcome};
+use crate::scripts::bit_board::{BitBoard};
+use crate::scripts::search_control::{CancelToken};
+use crate::scripts::trans_table::{Bound, Entry, Table};
+
+const INFINITY: u32 = u32::MAX;
+
+//solved results are stored in the table as bounds on the result for the player to move in that position,
+//so they stay valid whichever root they were proven from
+const WIN: i64 = 1;
+const DRAW: i64 = 0;
+const LOSS: i64 = -1;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Goal {
+    Win, //the root player wins
+    AtLeastDraw, //the root player wins or draws
+}
+
+//kept small since proofs can need millions of nodes
+struct Node {
+    parent: u32, //unused for the root
+    first_child: u32, //children are stored next to each other
+    child_count: u8, //0 for unexpanded and solved nodes
+    col: u8, //move that led to this node
+    attacker_to_move: bool, //OR node when the root player is to move, AND node otherwise
+    pn: u32, //proof number: leaves that still have to be proven
+    dn: u32, //disproof number
+}
+
+//best-first proof-number search over an explicit tree, proves or disproves a goal without any depth limit
+pub struct PnSearch<'a, T: Table> {
+    trans_table: &'a mut T,
+    cancel: &'a CancelToken,
+    max_nodes: usize,
+    nodes: Vec<Node>,
+}
+
+impl<'a, T: Table> PnSearch<'a, T> {
+    pub fn new(trans_table: &'a mut T, cancel: &'a CancelToken, max_nodes: usize) -> Self {
+        Self {
+            trans_table,
+            cancel,
+            max_nodes,
+            nodes: Vec::new(),
+        }
+    }
+
+    //win, draw or loss for the player to move, None if the node budget ran out or the search was cancelled
+    pub fn solve(&mut self, game: &BitBoard) -> Option<Outcome> {
+        if self.prove(game, Goal::Win)? {
+            return Some(Outcome::Win);
+        }
+
+        if self.prove(game, Goal::AtLeastDraw)? {
+            return Some(Outcome::Draw);
+        }
+
+        return Some(Outcome::Loss);
+    }
+
+    fn prove(&mut self, game: &BitBoard, goal: Goal) -> Option<bool> {
+        self.nodes.clear();
+        let (pn, dn) = self.evaluate(game, goal, true);
+        self.nodes.push(Node {
+            parent: 0,
+            first_child: 0,
+            child_count: 0,
+            col: 0,
+            attacker_to_move: true,
+            pn,
+            dn,
+        });
+
+        while self.nodes[0].pn != 0 && self.nodes[0].dn != 0 {
+            if self.nodes.len() >= self.max_nodes || self.cancel.is_cancelled() {
+                return None;
+            }
+
+            let mut board = game.clone();
+            let leaf = self.select_most_proving(&mut board);
+            self.expand(leaf, &mut board, goal);
+            self.update_ancestors(leaf, &mut board, goal);
+        }
+
+        return Some(self.nodes[0].pn == 0);
+    }
+
+    //walks down to a leaf, choosing the child that is cheapest to prove at OR nodes
+    //and cheapest to disprove at AND nodes
+    fn select_most_proving(&self, board: &mut BitBoard) -> usize {
+        let mut node = 0;
+
+        while self.nodes[node].child_count != 0 {
+            let children = self.children(node);
+            node = if self.nodes[node].attacker_to_move {
+                children.min_by_key(|&child| self.nodes[child].pn).unwrap()
+            } else {
+                children.min_by_key(|&child| self.nodes[child].dn).unwrap()
+            };
+            board.play_move(self.nodes[node].col as usize);
+        }
+
+        return node;
+    }
+
+    fn expand(&mut self, node: usize, board: &mut BitBoard, goal: Goal) {
+        let attacker_to_move = !self.nodes[node].attacker_to_move;
+        let moves = board.non_losing_moves();
+        self.nodes[node].first_child = self.nodes.len() as u32;
+
+        for col in 0..board.width() {
+            if moves & board.move_mask(col) != 0 {
+                board.play_move(col);
+                let (pn, dn) = self.evaluate(board, goal, attacker_to_move);
+                let _ = board.undo_move(col);
+
+                self.nodes.push(Node {
+                    parent: node as u32,
+                    first_child: 0,
+                    child_count: 0,
+                    col: col as u8,
+                    attacker_to_move,
+                    pn,
+                    dn,
+                });
+                self.nodes[node].child_count += 1;
+            }
+        }
+    }
+
+    //recomputes proof and disproof numbers from node up to the root,
+    //storing every newly solved position in the table on the way
+    fn update_ancestors(&mut self, mut node: usize, board: &mut BitBoard, goal: Goal) {
+        loop {
+            if self.nodes[node].child_count != 0 {
+                let pns = self.children(node).map(|child| self.nodes[child].pn);
+                let dns = self.children(node).map(|child| self.nodes[child].dn);
+                let (pn, dn) = if self.nodes[node].attacker_to_move {
+                    (pns.min().unwrap(), dns.fold(0, |sum: u32, dn| sum.saturating_add(dn)))
+                } else {
+                    (pns.fold(0, |sum: u32, pn| sum.saturating_add(pn)), dns.min().unwrap())
+                };
+
+                self.nodes[node].pn = pn;
+                self.nodes[node].dn = dn;
+
+                if pn == 0 || dn == 0 {
+                    self.store(board, goal, node);
+                    //solved subtrees are never visited again
+                    self.nodes[node].child_count = 0;
+                }
+            }
+
+            if node == 0 {
+                break;
+            }
+
+            let _ = board.undo_move(self.nodes[node].col as usize);
+            node = self.nodes[node].parent as usize;
+        }
+    }
+
+    fn children(&self, node: usize) -> std::ops::Range<usize> {
+        let first = self.nodes[node].first_child as usize;
+        return first..first + self.nodes[node].child_count as usize;
+    }
+
+    //proof and disproof numbers of a new leaf with board at its position
+    fn evaluate(&self, board: &BitBoard, goal: Goal, attacker_to_move: bool) -> (u32, u32) {
+        let (can_win, can_draw) = self.lookup(board);
+        //the goal seen from the player to move: the attacker's own goal,
+        //or for the defender the opposite goal with the answer flipped
+        let (known, flip) = match (goal, attacker_to_move) {
+            (Goal::Win, true) => (can_win, false),
+            (Goal::AtLeastDraw, true) => (can_draw, false),
+            (Goal::Win, false) => (can_draw, true),
+            (Goal::AtLeastDraw, false) => (can_win, true),
+        };
+
+        let mut moves = 0;
+        let proven = match known {
+            Some(result) => Some(result != flip),
+            None => {
+                if (0..board.width()).any(|col| board.is_move_valid(col) && board.is_winning_move(col)) {
+                    //the player to move wins at once
+                    Some(attacker_to_move)
+                } else if (0..board.width()).all(|col| !board.is_move_valid(col)) {
+                    Some(goal == Goal::AtLeastDraw)
+                } else {
+                    moves = board.non_losing_moves().count_ones();
+                    //every move hands the opponent a win
+                    if moves == 0 {Some(!attacker_to_move)} else {None}
+                }
+            }
+        };
+
+        //unsolved leaves start from their mobility: many replies make a node harder to refute
+        match proven {
+            Some(true) => (0, INFINITY),
+            Some(false) => (INFINITY, 0),
+            None if attacker_to_move => (1, moves),
+            None => (moves, 1),
+        }
+    }
+
+    //what the table knows about the player to move: (can force a win, can force at least a draw)
+    fn lookup(&self, board: &BitBoard) -> (Option<bool>, Option<bool>) {
+        let (lower, upper) = self.bounds(board);
+        let fact = |at_least: i64| {
+            if lower >= at_least {
+                Some(true)
+            } else if upper < at_least {
+                Some(false)
+            } else {
+                None
+            }
+        };
+
+        return (fact(WIN), fact(DRAW));
+    }
+
+    //lowest and highest result the player to move can get according to the table,
+    //only entries searched to the end of the game are facts
+    fn bounds(&self, board: &BitBoard) -> (i64, i64) {
+        let to_end = (board.width() * board.height() - board.get_num_moves()) as u8;
+
+        match self.trans_table.get(board.get_unique_key()).filter(|entry| entry.depth >= to_end) {
+            Some(Entry {value, bound: Bound::Exact, ..}) => (value, value),
+            Some(Entry {value, bound: Bound::Lower, ..}) => (value, WIN),
+            Some(Entry {value, bound: Bound::Upper, ..}) => (LOSS, value),
+            None => (LOSS, WIN),
+        }
+    }
+
+    fn store(&mut self, board: &BitBoard, goal: Goal, node: usize) {
+        let proven = self.nodes[node].pn == 0;
+        let attacker_to_move = self.nodes[node].attacker_to_move;
+        let at_least = match goal {
+            Goal::Win => WIN,
+            Goal::AtLeastDraw => DRAW,
+        };
+        let (mut lower, mut upper) = self.bounds(board);
+
+        //translate the goal result into a bound for the player to move, the defender's result is the opposite
+        match (attacker_to_move, proven) {
+            (true, true) => lower = lower.max(at_least),
+            (true, false) => upper = upper.min(at_least - 1),
+            (false, true) => upper = upper.min(-at_least),
+            (false, false) => lower = lower.max(1 - at_least),
+        }
+
+        //proofs look ahead to the end of the game
+        let depth = (board.width() * board.height() - board.get_num_moves()) as u8;
+        let entry = if lower == upper {
+            Entry {value: lower, bound: Bound::Exact, best_move: None, depth}
+        } else if lower > LOSS {
+            Entry {value: lower, bound: Bound::Lower, best_move: None, depth}
+        } else {
+            Entry {value: upper, bound: Bound::Upper, best_move: None, depth}
+        };
+        self.trans_table.insert(board.get_unique_key(), entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scripts::tablebase::{Tablebase};
+    use crate::scripts::trans_table::{TranspositionTable};
+
+    fn solve(game: &BitBoard, max_nodes: usize) -> Option<Outcome> {
+        let mut trans_table = TranspositionTable::with_budget(1 << 20);
+        let cancel = CancelToken::new();
+        return PnSearch::new(&mut trans_table, &cancel, max_nodes).solve(game);
+    }
+
+    fn play(line: &str) -> BitBoard {
+        let mut game = BitBoard::new();
+
+        for c in line.chars() {
+            game.play_move(c.to_digit(10).unwrap() as usize);
+        }
+
+        return game;
+    }
+
+    #[test]
+    fn solves_known_positions() {
+        assert_eq!(solve(&play("44444433"), 1 << 20), Some(Outcome::Win));
+        assert_eq!(solve(&play("42230230036642463"), 1 << 20), Some(Outcome::Draw));
+        //the opponent completes the bottom row whatever is played
+        assert_eq!(solve(&play("1122336"), 1 << 20), Some(Outcome::Loss));
+    }
+
+    #[test]
+    fn matches_the_tablebase() {
+        let tablebase = Tablebase::generate(4, 4);
+        //solved positions stay true, so one table serves every search
+        let mut trans_table = TranspositionTable::with_budget(1 << 20);
+        let cancel = CancelToken::new();
+
+        for (game, entry) in tablebase.positions().step_by(5) {
+            let outcome = PnSearch::new(&mut trans_table, &cancel, 1 << 20).solve(&game);
+            assert_eq!(outcome, Some(entry.outcome), "key {}", game.get_unique_key());
+        }
+    }
+
+    #[test]
+    fn gives_up_when_out_of_nodes() {
+        assert_eq!(solve(&BitBoard::new(), 1000), None);
+
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let mut trans_table = TranspositionTable::with_budget(1 << 20);
+        assert_eq!(PnSearch::new(&mut trans_table, &cancel, 1 << 20).solve(&play("3")), None);
+    }
+
+    #[test]
+    fn ignores_entries_short_of_the_end() {
+        let game = play("44444433");
+        let mut trans_table = TranspositionTable::with_budget(1 << 20);
+        let cancel = CancelToken::new();
+
+        //a depth limited search claiming a loss proves nothing
+        trans_table.insert(game.get_unique_key(), Entry {value: LOSS, bound: Bound::Exact, best_move: None, depth: 4});
+        assert_eq!(PnSearch::new(&mut trans_table, &cancel, 1 << 20).solve(&game), Some(Outcome::Win));
+    }
+}
@@ -0,0 +1,35 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+//shared flag asking a running search to stop, cloning it gives another handle to the same flag
+#[derive(Clone, Default, Debug)]
+pub struct CancelToken {
+    flag: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::Relaxed)
+    }
+}
+
+//snapshot of a running search, handed to progress callbacks
+#[derive(Copy, Clone, Default, Debug)]
+pub struct Progress {
+    pub root_move: Option<usize>, //column being searched at the root
+    pub depth: usize, //deepest ply below the root reached so far
+    pub best_move: Option<usize>, //best column among those finished
+    pub best_score: Option<i64>,
+    pub nodes: u64, //positions searched since the search started
+}
+
+//called with the progress of a search every so often while it runs
+pub type ProgressCallback = Box<dyn FnMut(&Progress) + Send>;
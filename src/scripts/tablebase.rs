@@ -0,0 +1,362 @@
+use crate::scripts::ai::{Outcome};
+use crate::scripts::bit_board::{BitBoard};
+#[cfg(not(target_arch = "wasm32"))]
+use std::collections::HashSet;
+
+//file layout: magic, version, width, height, bytes per key, entry count (u32 LE),
+//then one record per position sorted by canonical key: the key in as few bytes as the board needs
+//followed by the outcome in the top two bits and the distance in the other six
+const MAGIC: &[u8; 4] = b"C4TB";
+const VERSION: u8 = 1;
+const HEADER_SIZE: usize = 12;
+const DISTANCE_BITS: u8 = 6;
+const WIDTH: usize = 7; //widest board the engine can play
+pub const MAX_CELLS: usize = 25; //generate keeps whole plies in memory, boards past 5x5 have far too many positions
+
+//exact result of a position for the player to move
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct TablebaseEntry {
+    pub outcome: Outcome,
+    pub distance: usize, //moves left until the game ends, the winner hurries and the loser holds out
+}
+
+impl TablebaseEntry {
+    //score of the position in the same units as AIGame::negamax
+    pub fn score(&self, game: &BitBoard) -> i64 {
+        let cells = game.width() * game.height();
+        let score = ((cells + 2 - game.get_num_moves() - self.distance) / 2) as i64;
+
+        match self.outcome {
+            Outcome::Win => score,
+            Outcome::Draw => 0,
+            Outcome::Loss => -score,
+        }
+    }
+
+    //result one move earlier, seen by the player who made the move
+    fn before_move(&self) -> Self {
+        let outcome = match self.outcome {
+            Outcome::Win => Outcome::Loss,
+            Outcome::Draw => Outcome::Draw,
+            Outcome::Loss => Outcome::Win,
+        };
+
+        Self {
+            outcome,
+            distance: self.distance + 1,
+        }
+    }
+
+    //orders results from the mover's point of view: quick wins first, long losses before quick ones
+    fn rank(&self) -> (u8, i64) {
+        match self.outcome {
+            Outcome::Win => (2, -(self.distance as i64)),
+            Outcome::Draw => (1, 0),
+            Outcome::Loss => (0, self.distance as i64),
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        let outcome = match self.outcome {
+            Outcome::Loss => 0,
+            Outcome::Draw => 1,
+            Outcome::Win => 2,
+        };
+
+        (outcome << DISTANCE_BITS) | self.distance as u8
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        let outcome = match byte >> DISTANCE_BITS {
+            0 => Outcome::Loss,
+            1 => Outcome::Draw,
+            2 => Outcome::Win,
+            _ => return None,
+        };
+
+        Some(Self {
+            outcome,
+            distance: (byte & ((1 << DISTANCE_BITS) - 1)) as usize,
+        })
+    }
+}
+
+//every position of a small board that can come up in a game, solved exactly
+pub struct Tablebase {
+    width: usize,
+    height: usize,
+    keys: Vec<u64>, //canonical keys of running games, sorted for binary search
+    values: Vec<u8>, //packed TablebaseEntry of the position with the same index
+}
+
+impl Tablebase {
+    //boards a tablebase can describe and the engine can play on
+    pub fn is_size_supported(width: usize, height: usize) -> bool {
+        return width <= WIDTH && BitBoard::is_size_supported(width, height);
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    //every position in the table with its result, in key order
+    pub fn positions(&self) -> impl Iterator<Item = (BitBoard, TablebaseEntry)> + '_ {
+        self.keys.iter().zip(&self.values).filter_map(move |(&key, &value)| {
+            Some((BitBoard::from_key(self.width, self.height, key), TablebaseEntry::from_byte(value)?))
+        })
+    }
+
+    //None for finished games and boards of another size
+    pub fn get(&self, game: &BitBoard) -> Option<TablebaseEntry> {
+        if game.width() != self.width || game.height() != self.height {
+            return None;
+        }
+
+        let idx = self.keys.binary_search(&game.get_canonical_key()).ok()?;
+        TablebaseEntry::from_byte(self.values[idx])
+    }
+
+    //score of playing col for the player to move, in the same units as AIGame::score_move
+    pub fn move_score(&self, game: &mut BitBoard, col: usize) -> Option<i64> {
+        let result = move_result(game, col, &|board: &BitBoard| self.get(board))?;
+        Some(result.score(game))
+    }
+
+    //fastest win, else a draw, else the slowest loss
+    pub fn best_move(&self, game: &mut BitBoard) -> Option<usize> {
+        let mut best: Option<(usize, TablebaseEntry)> = None;
+
+        for col in 0..game.width() {
+            if game.is_move_valid(col) {
+                let result = move_result(game, col, &|board: &BitBoard| self.get(board))?;
+
+                if best.is_none_or(|(_, entry)| result.rank() > entry.rank()) {
+                    best = Some((col, result));
+                }
+            }
+        }
+
+        best.map(|(col, _)| col)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let key_bytes = key_bytes(self.width, self.height);
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + (key_bytes + 1) * self.keys.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.push(self.width as u8);
+        bytes.push(self.height as u8);
+        bytes.push(key_bytes as u8);
+        bytes.extend_from_slice(&(self.keys.len() as u32).to_le_bytes());
+
+        for (key, value) in self.keys.iter().zip(&self.values) {
+            bytes.extend_from_slice(&key.to_le_bytes()[..key_bytes]);
+            bytes.push(*value);
+        }
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < HEADER_SIZE || &bytes[0..4] != MAGIC {
+            return Err("Not a tablebase file!".to_string());
+        }
+
+        if bytes[4] != VERSION {
+            return Err(format!("Unsupported tablebase version {}", bytes[4]));
+        }
+
+        let width = bytes[5] as usize;
+        let height = bytes[6] as usize;
+
+        if !Self::is_size_supported(width, height) || bytes[7] as usize != key_bytes(width, height) {
+            return Err(format!("Invalid tablebase board size {}x{}", width, height));
+        }
+
+        let record_size = bytes[7] as usize + 1;
+        let count = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize;
+
+        if bytes.len() != HEADER_SIZE + count * record_size {
+            return Err("Tablebase is truncated!".to_string());
+        }
+
+        let mut keys = Vec::with_capacity(count);
+        let mut values = Vec::with_capacity(count);
+
+        for record in bytes[HEADER_SIZE..].chunks_exact(record_size) {
+            let mut key = [0; 8];
+            key[..record_size - 1].copy_from_slice(&record[..record_size - 1]);
+            keys.push(u64::from_le_bytes(key));
+            values.push(record[record_size - 1]);
+        }
+
+        if keys.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err("Tablebase entries are not sorted!".to_string());
+        }
+
+        Ok(Self {
+            width,
+            height,
+            keys,
+            values,
+        })
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: &str) -> Result<Self, String> {
+        let bytes = std::fs::read(path).map_err(|err| err.to_string())?;
+        Self::from_bytes(&bytes)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        std::fs::write(path, self.to_bytes()).map_err(|err| err.to_string())
+    }
+
+    //retrograde analysis: collects the running games of each ply going forward,
+    //then solves the plies from the last one back to the empty board
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn generate(width: usize, height: usize) -> Self {
+        let cells = width * height;
+        let mut layers: Vec<Vec<u64>> = vec![vec![BitBoard::with_size(width, height).get_canonical_key()]];
+
+        //games still running after every cell but the last is filled are the final layer
+        while layers.len() < cells {
+            let mut next = HashSet::new();
+
+            for &key in layers.last().unwrap() {
+                let mut game = BitBoard::from_key(width, height, key);
+
+                for col in 0..width {
+                    if game.is_move_valid(col) && !game.is_winning_move(col) {
+                        game.play_move(col);
+                        next.insert(game.get_canonical_key());
+                        let _ = game.undo_move(col);
+                    }
+                }
+            }
+
+            let mut next: Vec<u64> = next.into_iter().collect();
+            next.sort_unstable();
+            layers.push(next);
+        }
+
+        let mut solved: Vec<Vec<u8>> = vec![Vec::new(); layers.len()];
+
+        for ply in (0..layers.len()).rev() {
+            let (current, later) = solved.split_at_mut(ply + 1);
+            let next_keys: &[u64] = layers.get(ply + 1).map_or(&[], |keys| keys.as_slice());
+            let next_values: &[u8] = later.first().map_or(&[], |values| values.as_slice());
+            let lookup = |board: &BitBoard| {
+                let idx = next_keys.binary_search(&board.get_canonical_key()).ok()?;
+                TablebaseEntry::from_byte(next_values[idx])
+            };
+
+            current[ply] = layers[ply].iter().map(|&key| {
+                let mut game = BitBoard::from_key(width, height, key);
+                let moves: Vec<usize> = (0..width).filter(|&col| game.is_move_valid(col)).collect();
+                let best = moves.into_iter()
+                    .map(|col| move_result(&mut game, col, &lookup).expect("Child position missing from the next ply!"))
+                    .max_by_key(|entry| entry.rank())
+                    .expect("Running game without a valid move!");
+                best.to_byte()
+            }).collect();
+        }
+
+        //keys of different plies never collide since a key encodes the coins in every column
+        let mut entries: Vec<(u64, u8)> = layers.into_iter().flatten().zip(solved.into_iter().flatten()).collect();
+        entries.sort_unstable_by_key(|&(key, _)| key);
+
+        Self {
+            width,
+            height,
+            keys: entries.iter().map(|&(key, _)| key).collect(),
+            values: entries.iter().map(|&(_, value)| value).collect(),
+        }
+    }
+}
+
+//result for the player to move of playing col, with lookup solving the position that follows
+fn move_result<F>(game: &mut BitBoard, col: usize, lookup: &F) -> Option<TablebaseEntry>
+where
+    F: Fn(&BitBoard) -> Option<TablebaseEntry>,
+{
+    if game.is_winning_move(col) {
+        return Some(TablebaseEntry {outcome: Outcome::Win, distance: 1});
+    }
+
+    game.play_move(col);
+    let result = if game.get_num_moves() == game.width() * game.height() {
+        Some(TablebaseEntry {outcome: Outcome::Draw, distance: 1})
+    } else {
+        lookup(game).map(|reply| reply.before_move())
+    };
+    let _ = game.undo_move(col);
+
+    return result;
+}
+
+//smallest number of bytes holding any key of the board
+fn key_bytes(width: usize, height: usize) -> usize {
+    ((height + 1) * width).div_ceil(8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scripts::ai::{AIGame};
+    use crate::scripts::trans_table::{TranspositionTable};
+
+    #[test]
+    fn negamax_agrees_on_4x4() {
+        let tablebase = Tablebase::generate(4, 4);
+        let ai = AIGame::with_seed(1);
+        let mut trans_table = TranspositionTable::with_budget(1 << 20);
+        assert!(tablebase.len() > 0);
+
+        //16 cells are within the depth limit, so negamax is exact
+        for (mut game, entry) in tablebase.positions() {
+            let (_, score) = ai.search(&mut game, &mut trans_table);
+            assert_eq!(score, entry.score(&game), "key {}", game.get_unique_key());
+        }
+    }
+
+    #[test]
+    fn best_move_keeps_the_result() {
+        let tablebase = Tablebase::generate(4, 4);
+
+        for (mut game, entry) in tablebase.positions().step_by(3) {
+            let col = tablebase.best_move(&mut game).unwrap();
+            assert_eq!(tablebase.move_score(&mut game, col), Some(entry.score(&game)));
+        }
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let tablebase = Tablebase::generate(3, 4);
+        let copy = Tablebase::from_bytes(&tablebase.to_bytes()).unwrap();
+
+        assert_eq!((copy.width(), copy.height(), copy.len()), (3, 4, tablebase.len()));
+        assert!(tablebase.positions().zip(copy.positions()).all(|((a, x), (b, y))| a.get_unique_key() == b.get_unique_key() && x == y));
+        assert_eq!(copy.get(&BitBoard::new()), None);
+    }
+
+    #[test]
+    fn unsupported_sizes_are_rejected() {
+        //too wide for the engine, too tall for the diagonal shifts, empty
+        for (width, height) in [(8, 5), (1, 20), (0, 4)] {
+            let mut bytes = MAGIC.to_vec();
+            bytes.extend_from_slice(&[VERSION, width as u8, height as u8, key_bytes(width, height) as u8]);
+            bytes.extend_from_slice(&0u32.to_le_bytes());
+            assert!(Tablebase::from_bytes(&bytes).is_err(), "{}x{}", width, height);
+        }
+    }
+}
@@ -1,31 +1,912 @@
+#[cfg(feature = "parallel")]
+use std::sync::Arc;
+#[cfg(feature = "parallel")]
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+//how a stored value relates to the true score of the position
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Bound {
+    Exact,
+    Lower, //the score is at least the value, the search failed high
+    Upper, //the score is at most the value, the search failed low
+}
+
+//search result for one position
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Entry {
+    pub value: i64,
+    pub bound: Bound,
+    pub best_move: Option<usize>, //column to try first when the position is searched again
+    pub depth: u8, //how many moves ahead the result looked, deeper results are kept over shallower ones
+}
+
+//which entry of a full bucket gives way to a new one
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ReplacementPolicy {
+    AlwaysReplace, //the new entry always goes in, pushing out the one stored longest ago
+    DepthPreferred, //the new entry only pushes out a shallower one, or is dropped
+    TwoTier, //half of each bucket keeps the deepest entries, the other half the newest
+}
+
+//one stored entry, the slots of a bucket lie next to each other
+#[derive(Copy, Clone, Default, Eq, PartialEq)]
+struct Slot {
+    key: u32, //low 32 bits of the key
+    value: u8, //value and bound, see Slot::new, 0 for an empty slot
+    best_move: u8, //plus one, 0 for none
+    depth: u8,
+    age: u8, //generation of the search that stored it, 0 once the generations wrapped around
+}
+
+impl Slot {
+    //packs value and bound into a byte: bound in the lowest 2 bits, never 0 so 0 still means empty,
+    //value offset by 32 in the 6 bits above it, enough for any board the engine plays
+    fn new(key: u64, entry: Entry, age: u8) -> Self {
+        debug_assert!(entry.value >= -32 && entry.value < 32, "Score out of range for a table entry");
+        let bound = match entry.bound {
+            Bound::Exact => 1,
+            Bound::Lower => 2,
+            Bound::Upper => 3,
+        };
+
+        Self {
+            key: key as u32,
+            value: (((entry.value + 32) as u8) << 2) | bound,
+            best_move: entry.best_move.map_or(0, |col| col as u8 + 1),
+            depth: entry.depth,
+            age,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.value == 0
+    }
+
+    //the slot as one word, 0 for an empty one
+    #[cfg(feature = "parallel")]
+    fn to_bits(self) -> u64 {
+        (self.key as u64) << 32 | (self.value as u64) << 24 | (self.best_move as u64) << 16 | (self.depth as u64) << 8 | self.age as u64
+    }
+
+    #[cfg(feature = "parallel")]
+    fn from_bits(bits: u64) -> Self {
+        Self {
+            key: (bits >> 32) as u32,
+            value: (bits >> 24) as u8,
+            best_move: (bits >> 16) as u8,
+            depth: (bits >> 8) as u8,
+            age: bits as u8,
+        }
+    }
+
+    fn entry(&self) -> Option<Entry> {
+        let bound = match self.value & 3 {
+            1 => Bound::Exact,
+            2 => Bound::Lower,
+            3 => Bound::Upper,
+            _ => return None,
+        };
+
+        Some(Entry {
+            value: (self.value >> 2) as i64 - 32,
+            bound,
+            best_move: self.best_move.checked_sub(1).map(|col| col as usize),
+            depth: self.depth,
+        })
+    }
+}
+
+//slots looked through together for every key
+const BUCKET_SLOTS: usize = 4;
+//bytes taken by one slot: partial key, value with bound, best move, depth and age
+const ENTRY_BYTES: usize = 8;
+//saved table layout: magic, version, entry count (u32 LE), then every entry as its key (u64 LE),
+//value with bound, best move and depth bytes as in Slot
+const MAGIC: &[u8; 4] = b"C4TT";
+const VERSION: u8 = 1;
+const HEADER_SIZE: usize = 9;
+const RECORD_BYTES: usize = 11;
+//widest key the table accepts, the standard board takes 7 columns of 7 bits
+pub const MAX_KEY_BITS: usize = 49;
+//fewest buckets for which partial keys identify every key of MAX_KEY_BITS bits, see TranspositionTable
+const MIN_BUCKETS: usize = 1 << (MAX_KEY_BITS - 32);
+
+//hash table of search results with a bucket of slots per index. the number of buckets is prime and each
+//slot keeps only the low 32 bits of its key: keys sharing a bucket are equal modulo the bucket count, so by
+//the chinese remainder theorem the partial key tells them apart as long as buckets * 2^32 exceeds every key,
+//which holds for keys of up to MAX_KEY_BITS bits since the table has at least 2^17 buckets.
+//wider keys would collide, so boards failing fits_board must not be searched with it.
+//a slot takes 8 bytes where a whole key and value took 16, most of the memory saved over the old
+//8.4M entry table comes from sizing it by a byte budget instead, 16 MiB for the browser player.
+//entries from earlier searches are still found, but give way to current ones first
 pub struct TranspositionTable {
-    items: Vec<(u64, u64)>,
-    len: usize,
-}   
+    slots: Vec<Slot>,
+    policy: ReplacementPolicy,
+    generation: u8, //age given to new entries, see new_search
+}
 
 impl TranspositionTable {
-    pub fn new(size: usize) -> Self {
+    //two-tier replacement did best in solver benchmarks
+    pub fn with_budget(budget: usize) -> Self {
+        Self::with_policy(budget, ReplacementPolicy::TwoTier)
+    }
+
+    //largest table fitting in budget bytes, but never fewer than MIN_BUCKETS buckets
+    pub fn with_policy(budget: usize, policy: ReplacementPolicy) -> Self {
+        Self::with_buckets((budget / (ENTRY_BYTES * BUCKET_SLOTS)).max(2 * MIN_BUCKETS), policy)
+    }
+
+    //the largest prime number of buckets not above buckets. below MIN_BUCKETS only keys under
+    //buckets * 2^32 can be told apart, which still covers small boards
+    fn with_buckets(buckets: usize, policy: ReplacementPolicy) -> Self {
+        let buckets = prime_below(buckets.max(3));
+
+        Self {
+            slots: vec![Slot::default(); buckets * BUCKET_SLOTS],
+            policy,
+            generation: 1,
+        }
+    }
+
+    //whether the keys of a width x height board fit, each column takes height + 1 bits of a key
+    pub fn fits_board(width: usize, height: usize) -> bool {
+        (height + 1) * width <= MAX_KEY_BITS
+    }
+
+    //whether the partial key tells key apart from every other key of its bucket, see TranspositionTable
+    fn holds_key(&self, key: u64) -> bool {
+        key >> 32 < self.buckets() as u64
+    }
+
+    pub fn buckets(&self) -> usize {
+        self.slots.len() / BUCKET_SLOTS
+    }
+
+    pub fn bucket(&self, key: u64) -> usize {
+        (key % self.buckets() as u64) as usize
+    }
+
+    //ages every stored entry by one search, call it before each new search.
+    //when the generations run out every entry is aged out, else ones stored 255 searches ago would look current
+    pub fn new_search(&mut self) {
+        if self.generation == u8::MAX {
+            for slot in self.slots.iter_mut() {
+                slot.age = 0;
+            }
+        }
+
+        self.generation = next_generation(self.generation);
+    }
+
+    pub fn insert(&mut self, key: u64, entry: Entry) {
+        assert!(self.holds_key(key), "Key too wide for the transposition table!");
+        let first = self.bucket(key) * BUCKET_SLOTS;
+        let slot = Slot::new(key, entry, self.generation);
+        place(&mut self.slots[first..first + BUCKET_SLOTS], slot, key, self.policy, self.generation);
+    }
+
+    //entry stored for key, None if no slot of its bucket holds that position
+    pub fn get(&self, key: u64) -> Option<Entry> {
+        assert!(self.holds_key(key), "Key too wide for the transposition table!");
+        let first = self.bucket(key) * BUCKET_SLOTS;
+        let slot = self.slots[first..first + BUCKET_SLOTS].iter().find(|slot| slot.holds(key))?;
+
+        slot.entry()
+    }
+
+    //the deepest entries that fit in max_bytes, as they took the most work to find.
+    //keys are saved whole, so the entries can be loaded into a table of any size
+    pub fn to_bytes(&self, max_bytes: usize) -> Vec<u8> {
+        let mut used: Vec<usize> = (0..self.slots.len()).filter(|&idx| !self.slots[idx].is_empty()).collect();
+        used.sort_by_key(|&idx| std::cmp::Reverse(self.slots[idx].depth));
+        used.truncate(max_bytes.saturating_sub(HEADER_SIZE) / RECORD_BYTES);
+
+        let inverse = self.shift_inverse();
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + RECORD_BYTES * used.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&(used.len() as u32).to_le_bytes());
+
+        for idx in used {
+            let slot = self.slots[idx];
+            bytes.extend_from_slice(&self.full_key(idx, inverse).to_le_bytes());
+            bytes.extend_from_slice(&[slot.value, slot.best_move, slot.depth]);
+        }
+
+        bytes
+    }
+
+    //adds entries saved by to_bytes, they count as coming from an earlier search once the next one starts
+    pub fn load_bytes(&mut self, bytes: &[u8]) -> Result<(), String> {
+        if bytes.len() < HEADER_SIZE || &bytes[0..4] != MAGIC {
+            return Err("Not a transposition table file!".to_string());
+        }
+
+        if bytes[4] != VERSION {
+            return Err(format!("Unsupported transposition table version {}", bytes[4]));
+        }
+
+        let count = u32::from_le_bytes([bytes[5], bytes[6], bytes[7], bytes[8]]) as usize;
+
+        if bytes.len() != HEADER_SIZE + RECORD_BYTES * count {
+            return Err("Transposition table file is truncated!".to_string());
+        }
+
+        for record in bytes[HEADER_SIZE..].chunks_exact(RECORD_BYTES) {
+            let key = u64::from_le_bytes(record[0..8].try_into().unwrap());
+
+            if !self.holds_key(key) {
+                return Err("Transposition table file holds a key too wide for the table!".to_string());
+            }
+
+            let slot = Slot {
+                key: key as u32,
+                value: record[8],
+                best_move: record[9],
+                depth: record[10],
+                age: self.generation,
+            };
+            let entry = slot.entry().ok_or("Transposition table file holds an empty entry!")?;
+            self.insert(key, entry);
+        }
+
+        Ok(())
+    }
+
+    //inverse of 2^32 modulo the bucket count, which exists as the count is an odd prime
+    fn shift_inverse(&self) -> u128 {
+        let buckets = self.buckets() as u128;
+        return pow_mod((1 << 32) % buckets, buckets - 2, buckets);
+    }
+
+    //the one key below buckets * 2^32 with the bucket of idx and the low 32 bits stored there
+    fn full_key(&self, idx: usize, shift_inverse: u128) -> u64 {
+        let buckets = self.buckets() as u128;
+        let bucket = (idx / BUCKET_SLOTS) as u128;
+        let low = self.slots[idx].key as u128;
+        //key = low + 2^32 * high, with 2^32 * high = bucket - low modulo the bucket count
+        let high = (bucket + buckets - low % buckets) % buckets * shift_inverse % buckets;
+
+        return (low + (high << 32)) as u64;
+    }
+}
+
+//generation after generation, skipping 0 which is kept for entries aged out when they wrap around
+fn next_generation(generation: u8) -> u8 {
+    generation.checked_add(1).unwrap_or(1)
+}
+
+//contents of one slot of a bucket, for the replacement rules shared by both tables
+trait BucketSlot: Copy {
+    fn slot(&self) -> Slot;
+    fn holds(&self, key: u64) -> bool;
+}
+
+impl BucketSlot for Slot {
+    fn slot(&self) -> Slot {
+        *self
+    }
+
+    fn holds(&self, key: u64) -> bool {
+        !self.is_empty() && self.key == key as u32
+    }
+}
+
+//stores new, the slot for key, in bucket following policy. entries of earlier generations are stale:
+//they are still found, but give way to current ones first
+fn place<S: BucketSlot>(bucket: &mut [S], new: S, key: u64, policy: ReplacementPolicy, generation: u8) {
+    let depth = new.slot().depth;
+    let replaceable = |slot: Slot| slot.is_empty() || slot.age != generation;
+
+    //a position already stored is updated in place, unless a current entry looked further ahead
+    if let Some(idx) = bucket.iter().position(|slot| slot.holds(key)) {
+        let old = bucket[idx].slot();
+
+        if policy == ReplacementPolicy::AlwaysReplace || old.age != generation || depth >= old.depth {
+            bucket[idx] = new;
+        }
+        return;
+    }
+
+    match policy {
+        ReplacementPolicy::AlwaysReplace => push_front(bucket, new),
+        ReplacementPolicy::DepthPreferred => {
+            let idx = shallowest(bucket, generation);
+
+            if replaceable(bucket[idx].slot()) || depth >= bucket[idx].slot().depth {
+                bucket[idx] = new;
+            }
+        }
+        ReplacementPolicy::TwoTier => {
+            let (deep, recent) = bucket.split_at_mut(BUCKET_SLOTS / 2);
+            let idx = shallowest(deep, generation);
+
+            if replaceable(deep[idx].slot()) || depth >= deep[idx].slot().depth {
+                //a current entry pushed out of the deep tier still gets a place among the newest
+                let demoted = std::mem::replace(&mut deep[idx], new);
+
+                if !replaceable(demoted.slot()) {
+                    push_front(recent, demoted);
+                }
+            } else {
+                push_front(recent, new);
+            }
+        }
+    }
+}
+
+//first choice to give way in slots: an empty slot, then a stale one, then the shallowest
+fn shallowest<S: BucketSlot>(slots: &[S], generation: u8) -> usize {
+    (0..slots.len()).min_by_key(|&idx| {
+        let slot = slots[idx].slot();
+        (!slot.is_empty(), slot.age == generation, slot.depth)
+    }).unwrap()
+}
+
+//puts new first, moving the others back by one: the entries stay ordered from newest to oldest,
+//so once there is no empty slot left the oldest one, stale if any is, gets overwritten
+fn push_front<S: BucketSlot>(slots: &mut [S], new: S) {
+    let victim = slots.iter().position(|slot| slot.slot().is_empty()).unwrap_or(slots.len() - 1);
+    slots.copy_within(0..victim, 1);
+    slots[0] = new;
+}
+
+//base^exp modulo m, for m below 2^64
+fn pow_mod(mut base: u128, mut exp: u128, m: u128) -> u128 {
+    let mut result = 1 % m;
+
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = result * base % m;
+        }
+
+        base = base * base % m;
+        exp >>= 1;
+    }
+
+    return result;
+}
+
+//largest prime not above n, n at least 2
+fn prime_below(n: usize) -> usize {
+    let is_prime = |p: usize| p >= 2 && (2..).take_while(|d| d * d <= p).all(|d| !p.is_multiple_of(d));
+    return (2..=n).rev().find(|&p| is_prime(p)).unwrap_or(2);
+}
+
+//common interface for the single-threaded table and tables shared between search threads
+pub trait Table {
+    fn get(&self, key: u64) -> Option<Entry>;
+    fn insert(&mut self, key: u64, entry: Entry);
+}
+
+impl Table for TranspositionTable {
+    fn get(&self, key: u64) -> Option<Entry> {
+        TranspositionTable::get(self, key)
+    }
+
+    fn insert(&mut self, key: u64, entry: Entry) {
+        TranspositionTable::insert(self, key, entry)
+    }
+}
+
+//bytes taken by one slot of the shared table, two words
+#[cfg(feature = "parallel")]
+const SHARED_ENTRY_BYTES: usize = 16;
+
+//handle to one table used by several threads at once without locks, cloning it shares the same table.
+//it follows the rules of TranspositionTable, but keeps whole keys. threads writing the same bucket
+//at once can lose an entry, which only costs some work
+#[cfg(feature = "parallel")]
+#[derive(Clone)]
+pub struct SharedTable {
+    inner: Arc<SharedSlots>,
+}
+
+#[cfg(feature = "parallel")]
+struct SharedSlots {
+    slots: Vec<SharedSlot>,
+    policy: ReplacementPolicy,
+    generation: AtomicU8,
+}
+
+//a slot as the packed Slot and the key xor that word. each word is written atomically, but a reader
+//can get the words of two different writes: the key then comes out wrong and the torn slot is a miss
+#[cfg(feature = "parallel")]
+#[derive(Default)]
+struct SharedSlot {
+    data: AtomicU64,
+    check: AtomicU64,
+}
+
+#[cfg(feature = "parallel")]
+impl SharedSlot {
+    fn read(&self) -> KeyedSlot {
+        let data = self.data.load(Ordering::Relaxed);
+        let check = self.check.load(Ordering::Relaxed);
+
+        KeyedSlot {
+            slot: Slot::from_bits(data),
+            key: check ^ data,
+        }
+    }
+
+    fn write(&self, keyed: KeyedSlot) {
+        let data = keyed.slot.to_bits();
+        self.data.store(data, Ordering::Relaxed);
+        self.check.store(keyed.key ^ data, Ordering::Relaxed);
+    }
+}
+
+//slot read from the shared table together with its whole key
+#[cfg(feature = "parallel")]
+#[derive(Copy, Clone, Eq, PartialEq)]
+struct KeyedSlot {
+    slot: Slot,
+    key: u64,
+}
+
+#[cfg(feature = "parallel")]
+impl BucketSlot for KeyedSlot {
+    fn slot(&self) -> Slot {
+        self.slot
+    }
+
+    fn holds(&self, key: u64) -> bool {
+        !self.slot.is_empty() && self.key == key
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl SharedTable {
+    //largest table fitting in budget bytes, whole keys work with any number of buckets
+    pub fn with_policy(budget: usize, policy: ReplacementPolicy) -> Self {
+        let buckets = prime_below((budget / (SHARED_ENTRY_BYTES * BUCKET_SLOTS)).max(2));
+
         Self {
-            items: vec![(0, 0); size],    
-            len: size,
+            inner: Arc::new(SharedSlots {
+                slots: (0..buckets * BUCKET_SLOTS).map(|_| SharedSlot::default()).collect(),
+                policy,
+                generation: AtomicU8::new(1),
+            }),
+        }
+    }
+
+    //see TranspositionTable::new_search, every handle sees the new generation.
+    //call it while no search runs, aging out the entries rewrites every slot
+    pub fn new_search(&self) {
+        let generation = self.inner.generation.load(Ordering::Relaxed);
+
+        if generation == u8::MAX {
+            for slot in self.inner.slots.iter() {
+                let mut keyed = slot.read();
+
+                if !keyed.slot.is_empty() {
+                    keyed.slot.age = 0;
+                    slot.write(keyed);
+                }
+            }
+        }
+
+        self.inner.generation.store(next_generation(generation), Ordering::Relaxed);
+    }
+
+    pub fn get(&self, key: u64) -> Option<Entry> {
+        let bucket = self.bucket(key);
+        let keyed = bucket.iter().map(|slot| slot.read()).find(|keyed| keyed.holds(key))?;
+
+        keyed.slot.entry()
+    }
+
+    //reads the bucket, places the entry in a copy and writes back the slots that changed
+    pub fn insert(&self, key: u64, entry: Entry) {
+        let generation = self.inner.generation.load(Ordering::Relaxed);
+        let bucket = self.bucket(key);
+        let before: [KeyedSlot; BUCKET_SLOTS] = std::array::from_fn(|i| bucket[i].read());
+        let mut after = before;
+        let new = KeyedSlot {
+            slot: Slot::new(key, entry, generation),
+            key,
+        };
+
+        place(&mut after, new, key, self.inner.policy, generation);
+
+        for i in 0..BUCKET_SLOTS {
+            if after[i] != before[i] {
+                bucket[i].write(after[i]);
+            }
+        }
+    }
+
+    fn bucket(&self, key: u64) -> &[SharedSlot] {
+        let buckets = self.inner.slots.len() / BUCKET_SLOTS;
+        let first = (key % buckets as u64) as usize * BUCKET_SLOTS;
+        &self.inner.slots[first..first + BUCKET_SLOTS]
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl Table for SharedTable {
+    fn get(&self, key: u64) -> Option<Entry> {
+        SharedTable::get(self, key)
+    }
+
+    fn insert(&mut self, key: u64, entry: Entry) {
+        SharedTable::insert(self, key, entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scripts::ai::{AIGame};
+    use crate::scripts::bit_board::{BitBoard};
+    use crate::scripts::tablebase::Tablebase;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    //few enough slots that every search overflows them
+    const TINY_BUCKETS: usize = 7;
+
+    const POLICIES: [ReplacementPolicy; 3] = [
+        ReplacementPolicy::AlwaysReplace,
+        ReplacementPolicy::DepthPreferred,
+        ReplacementPolicy::TwoTier,
+    ];
+
+    fn entry(value: i64, depth: u8) -> Entry {
+        Entry {value, bound: Bound::Exact, best_move: Some(3), depth}
+    }
+
+    //keys filling one bucket, the first of them being key
+    fn bucket_keys(table: &TranspositionTable, key: u64, count: usize) -> Vec<u64> {
+        (0..count as u64).map(|i| key + i * table.buckets() as u64).collect()
+    }
+
+    #[test]
+    fn entries_round_trip() {
+        let mut table = TranspositionTable::with_budget(0);
+
+        for (key, bound) in [(1, Bound::Exact), (2, Bound::Lower), (3, Bound::Upper)] {
+            for value in [-21, -1, 0, 1, 21] {
+                let stored = Entry {value, bound, best_move: Some(6), depth: 20};
+                table.insert(key, stored);
+                assert_eq!(table.get(key), Some(stored));
+            }
+        }
+
+        table.insert(4, Entry {value: 5, bound: Bound::Upper, best_move: None, depth: 0});
+        assert_eq!(table.get(4).unwrap().best_move, None);
+    }
+
+    #[test]
+    fn empty_slots_miss() {
+        let table = TranspositionTable::with_budget(0);
+        assert!(table.buckets() >= MIN_BUCKETS);
+        assert_eq!(table.get(0), None);
+        assert_eq!(table.get(12345), None);
+    }
+
+    #[test]
+    fn keys_sharing_a_bucket_miss() {
+        let mut table = TranspositionTable::with_budget(0);
+        let buckets = table.buckets() as u64;
+        let key = BitBoard::new().board_mask() / 3;
+
+        table.insert(key, entry(7, 5));
+
+        //every other key that maps to the same bucket, up to the largest board key
+        for step in 1..200 {
+            for other in [key + step * buckets, key.wrapping_sub(step * buckets)] {
+                if other < 1 << 49 {
+                    assert_eq!(table.bucket(other), table.bucket(key));
+                    assert_eq!(table.get(other), None, "key {} read the entry of {}", other, key);
+                }
+            }
+        }
+
+        assert_eq!(table.get(key), Some(entry(7, 5)));
+    }
+
+    #[test]
+    fn a_bucket_holds_several_positions() {
+        for policy in POLICIES {
+            let mut table = TranspositionTable::with_policy(0, policy);
+            let keys = bucket_keys(&table, 1000, BUCKET_SLOTS);
+
+            for (i, &key) in keys.iter().enumerate() {
+                table.insert(key, entry(i as i64, 5));
+            }
+
+            for (i, &key) in keys.iter().enumerate() {
+                assert_eq!(table.get(key), Some(entry(i as i64, 5)), "{:?}", policy);
+            }
+        }
+    }
+
+    #[test]
+    fn always_replace_keeps_the_newest() {
+        let mut table = TranspositionTable::with_policy(0, ReplacementPolicy::AlwaysReplace);
+        let keys = bucket_keys(&table, 1000, BUCKET_SLOTS + 1);
+
+        for (i, &key) in keys.iter().enumerate() {
+            table.insert(key, entry(0, 30 - i as u8));
+        }
+
+        assert_eq!(table.get(keys[0]), None);
+        assert!(keys[1..].iter().all(|&key| table.get(key).is_some()));
+    }
+
+    #[test]
+    fn depth_preferred_keeps_the_deepest() {
+        let mut table = TranspositionTable::with_policy(0, ReplacementPolicy::DepthPreferred);
+        let keys = bucket_keys(&table, 1000, BUCKET_SLOTS + 2);
+
+        for (i, &key) in keys[..BUCKET_SLOTS].iter().enumerate() {
+            table.insert(key, entry(0, 10 + i as u8));
+        }
+
+        //too shallow to get in
+        table.insert(keys[BUCKET_SLOTS], entry(0, 9));
+        assert_eq!(table.get(keys[BUCKET_SLOTS]), None);
+
+        //pushes out the shallowest
+        table.insert(keys[BUCKET_SLOTS + 1], entry(0, 11));
+        assert_eq!(table.get(keys[BUCKET_SLOTS + 1]), Some(entry(0, 11)));
+        assert_eq!(table.get(keys[0]), None);
+        assert!(keys[1..BUCKET_SLOTS].iter().all(|&key| table.get(key).is_some()));
+
+        //a stored position is not overwritten by a shallower result
+        table.insert(keys[1], entry(4, 2));
+        assert_eq!(table.get(keys[1]), Some(entry(0, 11)));
+    }
+
+    #[test]
+    fn two_tier_keeps_the_deepest_and_the_newest() {
+        let mut table = TranspositionTable::with_policy(0, ReplacementPolicy::TwoTier);
+        let keys = bucket_keys(&table, 1000, 3 * BUCKET_SLOTS);
+        let (deep, shallow) = keys.split_at(BUCKET_SLOTS / 2);
+
+        for &key in deep {
+            table.insert(key, entry(0, 30));
+        }
+
+        for (i, &key) in shallow.iter().enumerate() {
+            table.insert(key, entry(0, 10 - (i % 2) as u8));
+        }
+
+        assert!(deep.iter().all(|&key| table.get(key).is_some()));
+        assert!(shallow[shallow.len() - BUCKET_SLOTS / 2..].iter().all(|&key| table.get(key).is_some()));
+        assert!(shallow[..shallow.len() - BUCKET_SLOTS / 2].iter().all(|&key| table.get(key).is_none()));
+    }
+
+    #[test]
+    fn stale_entries_are_found_but_replaced_first() {
+        for policy in POLICIES {
+            let mut table = TranspositionTable::with_policy(0, policy);
+            let keys = bucket_keys(&table, 1000, 2 * BUCKET_SLOTS);
+            let (old, new) = keys.split_at(BUCKET_SLOTS);
+
+            for &key in old {
+                table.insert(key, entry(1, 30));
+            }
+
+            table.new_search();
+            assert!(old.iter().all(|&key| table.get(key) == Some(entry(1, 30))), "{:?}", policy);
+
+            //shallow results of the current search take the place of deep ones from the last
+            for &key in new {
+                table.insert(key, entry(2, 1));
+            }
+
+            assert!(new.iter().all(|&key| table.get(key) == Some(entry(2, 1))), "{:?}", policy);
+            assert!(old.iter().all(|&key| table.get(key).is_none()), "{:?}", policy);
+        }
+    }
+
+    //after 256 searches the generation comes back to the one the old entries were stored in
+    #[test]
+    fn entries_stay_stale_when_generations_wrap() {
+        let mut table = TranspositionTable::with_policy(0, ReplacementPolicy::DepthPreferred);
+        let keys = bucket_keys(&table, 1000, 2 * BUCKET_SLOTS);
+        let (old, new) = keys.split_at(BUCKET_SLOTS);
+
+        for &key in old {
+            table.insert(key, entry(1, 30));
+        }
+
+        for _ in 0..256 {
+            table.new_search();
+        }
+
+        for &key in new {
+            table.insert(key, entry(2, 1));
+        }
+
+        assert!(new.iter().all(|&key| table.get(key) == Some(entry(2, 1))));
+    }
+
+    #[test]
+    fn saved_entries_load_into_a_table_of_another_size() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut table = TranspositionTable::with_budget(0);
+        let keys: Vec<u64> = (0..1000).map(|_| rng.gen_range(0..1 << 49)).collect();
+
+        for (i, &key) in keys.iter().enumerate() {
+            table.insert(key, Entry {value: i as i64 % 40 - 20, bound: Bound::Lower, best_move: Some(i % 7), depth: i as u8});
+        }
+
+        let mut loaded = TranspositionTable::with_budget(16 << 20);
+        loaded.load_bytes(&table.to_bytes(usize::MAX)).unwrap();
+
+        for &key in &keys {
+            assert_eq!(loaded.get(key), table.get(key));
+        }
+    }
+
+    #[test]
+    fn saving_within_a_budget_keeps_the_deepest_entries() {
+        let mut table = TranspositionTable::with_budget(0);
+
+        for key in 0..100 {
+            table.insert(key, entry(1, key as u8));
+        }
+
+        let mut loaded = TranspositionTable::with_budget(0);
+        loaded.load_bytes(&table.to_bytes(HEADER_SIZE + 10 * RECORD_BYTES)).unwrap();
+        assert!((0..90).all(|key| loaded.get(key).is_none()));
+        assert!((90..100).all(|key| loaded.get(key) == Some(entry(1, key as u8))));
+    }
+
+    #[test]
+    fn loading_rejects_broken_files() {
+        let mut table = TranspositionTable::with_budget(0);
+        table.insert(5, entry(3, 9));
+        let bytes = table.to_bytes(usize::MAX);
+
+        assert!(table.load_bytes(b"C4NT").is_err());
+        assert!(table.load_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(table.load_bytes(&bytes).is_ok());
+
+        //a key too wide for partial keys to tell apart
+        let mut wide = bytes.clone();
+        wide[HEADER_SIZE + 7] = 1;
+        assert!(table.load_bytes(&wide).is_err());
+    }
+
+    #[test]
+    fn only_boards_with_narrow_keys_fit() {
+        assert!(TranspositionTable::fits_board(7, 6));
+        assert!(TranspositionTable::fits_board(5, 4));
+        assert!(!TranspositionTable::fits_board(7, 7));
+        assert!(!TranspositionTable::fits_board(8, 6));
+    }
+
+    #[test]
+    #[should_panic(expected = "Key too wide")]
+    fn wide_keys_are_rejected() {
+        let mut table = TranspositionTable::with_buckets(TINY_BUCKETS, ReplacementPolicy::TwoTier);
+        table.insert(3 << 32, entry(0, 1));
+        table.insert(7 << 32, entry(0, 1));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn shared_table_keeps_whole_keys() {
+        let table = SharedTable::with_policy(0, ReplacementPolicy::TwoTier);
+        let keys = [5, 5 + (2 << 32), 5 + (6 << 40)];
+
+        for (i, &key) in keys.iter().enumerate() {
+            table.insert(key, entry(i as i64, 3));
+        }
+
+        for (i, &key) in keys.iter().enumerate() {
+            assert_eq!(table.get(key), Some(entry(i as i64, 3)));
         }
+
+        assert_eq!(table.get(7 + (2 << 32)), None);
     }
 
-    pub fn index(&self, key: u64) -> usize {
-        (key as usize) % self.items.len()
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn shared_entries_stay_stale_when_generations_wrap() {
+        let table = SharedTable::with_policy(0, ReplacementPolicy::DepthPreferred);
+        let keys: Vec<u64> = (0..2 * BUCKET_SLOTS as u64).map(|i| 1000 + i * table.inner.slots.len() as u64 / BUCKET_SLOTS as u64).collect();
+        let (old, new) = keys.split_at(BUCKET_SLOTS);
+
+        for &key in old {
+            table.insert(key, entry(1, 30));
+        }
+
+        for _ in 0..256 {
+            table.new_search();
+        }
+
+        for &key in new {
+            table.insert(key, entry(2, 1));
+        }
+
+        assert!(new.iter().all(|&key| table.get(key) == Some(entry(2, 1))));
     }
 
-    pub fn insert(&mut self, key: u64, val: u64) {
-        let idx: usize = self.index(key);
-        self.items[idx] = (key, val);
+    //threads hammering the same two buckets must never read an entry made of two different writes
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn shared_table_never_returns_torn_entries() {
+        let table = SharedTable::with_policy(0, ReplacementPolicy::TwoTier);
+        //every field follows from the key, so any mix of two writes shows
+        let expected = |key: u64| Entry {
+            value: (key % 61) as i64 - 30,
+            bound: [Bound::Exact, Bound::Lower, Bound::Upper][(key % 3) as usize],
+            best_move: Some((key % 7) as usize),
+            depth: (key % 251) as u8,
+        };
+
+        std::thread::scope(|scope| {
+            for thread in 0..8u64 {
+                let mut table = table.clone();
+
+                scope.spawn(move || {
+                    let mut rng = StdRng::seed_from_u64(thread);
+
+                    for _ in 0..50_000 {
+                        let key = rng.gen_range(0..4) << 40 | rng.gen_range(0..6);
+                        Table::insert(&mut table, key, expected(key));
+
+                        let key = rng.gen_range(0..4) << 40 | rng.gen_range(0..6);
+                        if let Some(found) = Table::get(&table, key) {
+                            assert_eq!(found, expected(key), "key {}", key);
+                        }
+                    }
+                });
+            }
+        });
     }
 
-    pub fn get(&self, key: u64) -> u64 {
-        let idx: usize = self.index(key);
-        self.items[idx].1
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn lazy_smp_with_tiny_shared_table_matches_the_tablebase() {
+        let tablebase = Tablebase::generate(4, 4);
+        let shared = SharedTable::with_policy(TINY_BUCKETS * BUCKET_SLOTS * SHARED_ENTRY_BYTES, ReplacementPolicy::TwoTier);
+        let cancel = crate::scripts::search_control::CancelToken::new();
+
+        for (game, entry) in tablebase.positions().step_by(5) {
+            shared.new_search();
+            let (_, score) = crate::scripts::lazy_smp::search(&game, &shared, 4, &cancel, None).unwrap();
+            assert_eq!(score, entry.score(&game), "key {}", game.get_unique_key());
+        }
     }
 
-    pub fn reset(&mut self) {
-        self.items = vec![(0, 0); self.len];
+    //a table of a few buckets shared by every position overflows and has its slots fought over,
+    //yet every score has to match the tablebase
+    #[test]
+    fn negamax_with_tiny_table_matches_the_tablebase() {
+        let tablebase = Tablebase::generate(4, 4);
+        let ai = AIGame::with_seed(1);
+
+        for policy in POLICIES {
+            let mut tiny = TranspositionTable::with_buckets(TINY_BUCKETS, policy);
+
+            for (mut game, entry) in tablebase.positions() {
+                tiny.new_search();
+                let (col, score) = ai.search(&mut game, &mut tiny);
+                assert_eq!(score, entry.score(&game), "{:?}, key {}", policy, game.get_unique_key());
+                assert_eq!(tablebase.move_score(&mut game, col), Some(score), "{:?}, key {}", policy, game.get_unique_key());
+            }
+        }
+    }
+
+    #[test]
+    fn proof_number_search_with_tiny_table_matches_the_tablebase() {
+        let tablebase = Tablebase::generate(4, 4);
+        let ai = AIGame::with_seed(1);
+
+        for policy in POLICIES {
+            let mut tiny = TranspositionTable::with_buckets(TINY_BUCKETS, policy);
+
+            for (game, entry) in tablebase.positions() {
+                tiny.new_search();
+                assert_eq!(ai.pn_solve(&game, &mut tiny), Some(entry.outcome), "{:?}, key {}", policy, game.get_unique_key());
+            }
+        }
     }
-}
\ No newline at end of file
+}